@@ -2,21 +2,74 @@
 // Licensed under the MIT License or the Apache License, Version 2.0.
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use crate::{ip_addresses::parse_ip_or_range, utils::parse_float_into_duration};
+use crate::{
+    columns::Column,
+    ip_addresses::{AddrFamily, parse_ip_or_range},
+    probe::Proto,
+    rules::{
+        RuleOverrides, StatusRules, load_config, parse_dwell, parse_flappy, parse_laggy,
+        parse_lossy, parse_unreachable,
+    },
+    sim::Simulation,
+    sockopts::{Dscp, SockOpts, parse_ttl},
+    targets::{TargetOpts, TargetSpec, expand_target, parse_targets_file},
+    utils::{parse_float_into_duration, parse_float_into_duration_or_zero},
+    voip::Codec,
+};
 use clap::{Parser, crate_authors, crate_description, crate_name, crate_version, value_parser};
-use std::{collections::HashSet, fmt::Debug, net::IpAddr, process, time::Duration};
+use std::{
+    collections::HashSet, fmt::Debug, net::IpAddr, path::PathBuf, process, str::FromStr, sync::Arc,
+    time::Duration,
+};
+
+/// Allowed range for ping interval (global or per target)
+pub(crate) const INTERVAL_MIN: Duration = Duration::from_millis(10);
+pub(crate) const INTERVAL_MAX: Duration = Duration::from_secs(10);
+/// Allowed range for ping timeout (global or per target)
+pub(crate) const TIMEOUT_MIN: Duration = Duration::from_millis(10);
+pub(crate) const TIMEOUT_MAX: Duration = Duration::from_secs(5);
 
 /// Configuration struct for the program.
 #[derive(Parser, Default, Debug, Clone)]
 #[command(name = crate_name!(), version = crate_version!(), author = crate_authors!(), about = crate_description!())]
 pub(crate) struct MpConfig {
     #[arg(
-        required = true,
+        required_unless_present_any = ["file", "simulate"],
         value_name = "IP1 [IP2...]",
-        help = "Space separated list of IP addresses or ranges to monitor"
+        help = "Space separated list of IP addresses, ranges or hostnames to monitor"
     )]
     pub targets: Vec<String>,
 
+    #[arg(
+        long,
+        short = 'f',
+        value_name = "PATH",
+        help = "Read targets from a file (one per line: TARGET [label=..] [group=..] [interval=..] [timeout=..] [laggy=..] etc)"
+    )]
+    pub file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PROFILE",
+        conflicts_with_all = ["targets", "file"],
+        help = "Simulate targets described in a TOML profile instead of probing the network"
+    )]
+    pub simulate: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write a summary of the run (counts and loss bursts per target) as TOML on exit"
+    )]
+    pub export: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read settings from a TOML config file (status rules in a [status] table)"
+    )]
+    pub config: Option<PathBuf>,
+
     #[arg(
         long,
         value_name = "IP1[,IP2...]",
@@ -48,6 +101,16 @@ pub(crate) struct MpConfig {
     )]
     pub timeout: Duration,
 
+    #[arg(
+        long,
+        value_name = "SECS",
+        required = false,
+        value_parser = parse_float_into_duration_or_zero,
+        default_value = "1",
+        help = "Keep waiting this long after the timeout for late replies, 0 to disable [0-10]"
+    )]
+    pub late_grace: Duration,
+
     #[arg(
         long,
         short = 's',
@@ -59,6 +122,45 @@ pub(crate) struct MpConfig {
     )]
     pub size: u16,
 
+    #[arg(
+        long,
+        value_name = "PORT",
+        value_parser = value_parser!(u16).range(1..),
+        help = "Probe targets with TCP handshakes to PORT instead of ICMP echo (per target: HOST:PORT)"
+    )]
+    pub tcp: Option<u16>,
+
+    #[arg(
+        long,
+        value_name = "PORT",
+        conflicts_with = "tcp",
+        value_parser = value_parser!(u16).range(1..),
+        help = "Probe targets with UDP datagrams to PORT instead of ICMP echo (echo or port unreachable)"
+    )]
+    pub udp: Option<u16>,
+
+    #[arg(
+        long,
+        value_name = "NUM",
+        value_parser = parse_ttl,
+        help = "TTL (IPv4) or hop limit (IPv6) of outgoing ICMP probes [1-255, default: system]"
+    )]
+    pub ttl: Option<u8>,
+
+    #[arg(
+        long,
+        value_name = "DSCP",
+        value_parser = Dscp::from_str,
+        help = "DSCP marking of outgoing ICMP probes: 0-63 or a name like ef, af41, cs5, be [default: none]"
+    )]
+    pub dscp: Option<Dscp>,
+
+    #[arg(
+        long,
+        help = "Set the don't-fragment flag on outgoing ICMP probes [default: system]"
+    )]
+    pub df: bool,
+
     #[arg(long, short = 'R', help = "Randomize ICMP payload data [default: no]")]
     pub randomize: bool,
 
@@ -83,6 +185,56 @@ pub(crate) struct MpConfig {
     )]
     pub detailed: u16,
 
+    #[arg(
+        long,
+        value_name = "NUM",
+        required = false,
+        value_parser = value_parser!(u32).range(1..1000),
+        default_value = "3",
+        help = "Consecutive lost probes after which a target is considered down (outage) [1-999]"
+    )]
+    pub outage_after: u32,
+
+    #[arg(
+        long,
+        value_name = "N:RATIO",
+        value_parser = parse_lossy,
+        help = "Status is lossy if at least RATIO of the last N probes are lost [default: 5:0.5]"
+    )]
+    pub lossy: Option<(usize, f64)>,
+
+    #[arg(
+        long,
+        value_name = "N:TRANSITIONS",
+        value_parser = parse_flappy,
+        help = "Status is flappy if the last N probes flip between lost and answered TRANSITIONS times [default: 10:5]"
+    )]
+    pub flappy: Option<(usize, usize)>,
+
+    #[arg(
+        long,
+        value_name = "N:FACTOR",
+        value_parser = parse_laggy,
+        help = "Status is laggy if the mean RTT of the last N replies is FACTOR times the overall mean [default: 10:2.0]"
+    )]
+    pub laggy: Option<(usize, f64)>,
+
+    #[arg(
+        long,
+        value_name = "NUM",
+        value_parser = parse_unreachable,
+        help = "Status is not reachable if none of the first NUM probes were answered [default: 10]"
+    )]
+    pub unreachable_after: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = parse_dwell,
+        help = "Time a new OK/lossy/flappy/laggy status must persist before it is shown [default: 0]"
+    )]
+    pub dwell: Option<Duration>,
+
     #[arg(
         long,
         value_name = "ms",
@@ -93,6 +245,56 @@ pub(crate) struct MpConfig {
     )]
     pub refresh: u64,
 
+    #[arg(
+        long,
+        value_name = "COL1,COL2...",
+        value_delimiter = ',',
+        value_parser = Column::from_str,
+        default_value = "sent,recv,loss,last,mean,min,max,stdev",
+        help = "Statistics columns to show: sent,recv,loss,last,mean,min,max,stdev,jitter,mos,rfactor,late,dup,reord,ttl,avail,outages and RTT percentiles as pNN (eg. p50,p99.9)"
+    )]
+    pub columns: Vec<Column>,
+
+    #[arg(
+        long,
+        value_name = "CODEC",
+        value_parser = Codec::from_str,
+        default_value = "g711",
+        help = "Codec impairment profile for the MOS/R-factor estimate: g711, g729, g723"
+    )]
+    pub codec: Codec,
+
+    #[arg(
+        long,
+        short = '4',
+        conflicts_with = "ipv6",
+        help = "Prefer IPv4 (A records) when resolving hostnames"
+    )]
+    pub ipv4: bool,
+
+    #[arg(
+        long,
+        short = '6',
+        help = "Prefer IPv6 (AAAA records) when resolving hostnames"
+    )]
+    pub ipv6: bool,
+
+    #[arg(
+        long,
+        value_name = "SECS",
+        required = false,
+        value_parser = parse_float_into_duration,
+        default_value = "60",
+        help = "Interval for re-resolving hostname targets [1-86400]"
+    )]
+    pub resolve: Duration,
+
+    #[arg(
+        long,
+        help = "Use unprivileged ICMP datagram sockets (no CAP_NET_RAW needed) [default: only as fallback]"
+    )]
+    pub unprivileged: bool,
+
     #[arg(long, short = 'v', help = "Increase output verbosity")]
     pub verbose: bool,
 
@@ -100,7 +302,14 @@ pub(crate) struct MpConfig {
     pub debug: bool,
 
     #[arg(skip)]
-    pub addrs: Vec<IpAddr>,
+    pub specs: Vec<TargetSpec>,
+
+    #[arg(skip)]
+    pub sim: Option<Arc<Simulation>>,
+
+    /// Effective global status rules (command line over config file over defaults)
+    #[arg(skip)]
+    pub rules: StatusRules,
 
     #[arg(skip)]
     pub ver: String,
@@ -112,89 +321,66 @@ impl MpConfig {
         let mut config: MpConfig = <MpConfig as Parser>::parse();
         config.ver = crate_version!().to_string();
 
-        // Parse all targets and expand them into individual IPs
-        let mut all_addrs: Vec<IpAddr> = Vec::new();
-        for target in &config.targets {
-            match parse_ip_or_range(target) {
-                Ok(mut ips) => {
-                    if config.verbose {
-                        if ips.len() > 1 {
-                            eprintln!("Expanded '{target}' to {} addresses", ips.len());
-                        }
-                    }
-                    all_addrs.append(&mut ips);
-                }
+        if let Some(path) = &config.simulate {
+            config.sim = match Simulation::load(path) {
+                Ok(sim) => Some(sim.into()),
                 Err(e) => {
-                    eprintln!("Error parsing target '{target}': {e}");
+                    eprintln!("Error loading simulation profile: {e}");
                     process::exit(1);
                 }
-            }
+            };
         }
-
-        // Remove duplicates while preserving order
-        let mut seen: HashSet<IpAddr> = HashSet::new();
-        all_addrs.retain(|ip: &IpAddr| seen.insert(*ip));
-
-        // Parse exclusions and expand them into individual IPs
-        let mut exclusions: HashSet<IpAddr> = HashSet::new();
-        for exc in &config.exclude {
-            match parse_ip_or_range(exc) {
-                Ok(mut ips) => {
-                    if config.verbose {
-                        if ips.len() > 1 {
-                            eprintln!("Expanded '{exc}' to {} addresses (exclusion)", ips.len());
-                        }
-                    }
-                    exclusions.extend(ips.drain(..));
-                }
+        let file_rules: RuleOverrides = match &config.config {
+            Some(path) => match load_config(path) {
+                Ok(rules) => rules,
                 Err(e) => {
-                    eprintln!("Error parsing exclusion '{exc}': {e}");
+                    eprintln!("Error loading config file: {e}");
                     process::exit(1);
                 }
-            }
-        }
+            },
+            None => RuleOverrides::default(),
+        };
+        let cli_rules: RuleOverrides = RuleOverrides {
+            lossy: config.lossy,
+            flappy: config.flappy,
+            laggy: config.laggy,
+            unreachable: config.unreachable_after,
+            dwell: config.dwell,
+        };
+        config.rules = cli_rules.or(&file_rules).apply(&StatusRules::default());
 
-        // Apply exclusions if needed
-        if !exclusions.is_empty() {
-            // let's see if we actually exclude anything
-            let remainder: HashSet<IpAddr> = &seen - &exclusions;
-            if remainder == seen {
-                eprintln!("WARN: exclusions did not match any target addresses.");
-            } else if remainder.is_empty() {
-                eprintln!("All target addresses were excluded.");
+        config.specs = match config.load_specs(true) {
+            Ok(specs) => specs,
+            Err(e) => {
+                eprintln!("{e}");
                 process::exit(1);
-            } else {
-                if config.verbose {
-                    eprintln!(
-                        "Excluding {} addresses from target list",
-                        (seen.len() - remainder.len())
-                    );
-                }
-                all_addrs.retain(|ip: &IpAddr| !exclusions.contains(ip));
             }
-        }
-
-        config.addrs = all_addrs;
-        if config.addrs.is_empty() {
-            eprintln!("No valid IP addresses provided.");
-            process::exit(1);
-        } else if config.verbose {
-            eprintln!("Total unique addresses to monitor: {}", config.addrs.len());
+        };
+        if config.verbose {
+            eprintln!("Total unique addresses to monitor: {}", config.specs.len());
         }
 
         // clamp interval between 10ms and 10s...
         config.interval = match config.interval {
-            d if d < Duration::from_millis(10) => Duration::from_millis(10),
-            d if d > Duration::from_secs(10) => Duration::from_secs(10),
+            d if d < INTERVAL_MIN => INTERVAL_MIN,
+            d if d > INTERVAL_MAX => INTERVAL_MAX,
             d => d,
         };
         // ... and timeout between 10ms and 5s
         config.timeout = match config.timeout {
-            d if d < Duration::from_millis(10) => Duration::from_millis(10),
-            d if d > Duration::from_secs(5) => Duration::from_secs(5),
+            d if d < TIMEOUT_MIN => TIMEOUT_MIN,
+            d if d > TIMEOUT_MAX => TIMEOUT_MAX,
             d => d,
         };
 
+        // ... and late reply grace period up to 10s
+        config.late_grace = config.late_grace.min(Duration::from_secs(10));
+
+        // ... and hostname re-resolution between 1s and 24h
+        config.resolve = config
+            .resolve
+            .clamp(Duration::from_secs(1), Duration::from_secs(86400));
+
         // If necessary, tweak the timeout so that we can't have an excessive number of
         // pending pings (tasks) to the same target. This is a simple heuristic to avoid
         // overwhelming the application with too many concurrent pings if the user has
@@ -214,4 +400,125 @@ impl MpConfig {
 
         config
     }
+
+    /// Expand all targets (command line and targets file) into [TargetSpec]s,
+    /// removing duplicates and applying exclusions. In simulation mode the
+    /// simulated targets are returned as-is.
+    ///
+    /// Informational messages are printed to stderr only if `log` is set, since
+    /// this is also called while the TUI is active (targets file reload).
+    pub fn load_specs(&self, log: bool) -> Result<Vec<TargetSpec>, String> {
+        let verbose: bool = log && self.verbose;
+        if let Some(sim) = &self.sim {
+            return Ok(sim.specs.clone());
+        }
+
+        // Parse all targets and expand them into individual IPs (or resolved hostnames)
+        let family: AddrFamily = self.family();
+        let mut all_specs: Vec<TargetSpec> = Vec::new();
+        for target in &self.targets {
+            let mut specs: Vec<TargetSpec> = expand_target(target, family)
+                .map_err(|e| format!("Error parsing target '{target}': {e}"))?;
+            if verbose {
+                if specs.len() > 1 {
+                    eprintln!("Expanded '{target}' to {} addresses", specs.len());
+                } else if specs[0].host.is_some() {
+                    eprintln!("Resolved '{target}' to {}", specs[0].addr);
+                }
+            }
+            all_specs.append(&mut specs);
+        }
+
+        // Targets file entries follow the command line targets
+        if let Some(path) = &self.file {
+            let mut specs: Vec<TargetSpec> = parse_targets_file(path, family)
+                .map_err(|e| format!("Error parsing targets file: {e}"))?;
+            if verbose {
+                eprintln!("Read {} targets from {}", specs.len(), path.display());
+            }
+            all_specs.append(&mut specs);
+        }
+
+        // Remove duplicates while preserving order
+        let mut seen: HashSet<String> = HashSet::new();
+        all_specs.retain(|s: &TargetSpec| seen.insert(s.key()));
+
+        // Parse exclusions and expand them into individual IPs
+        let mut exclusions: HashSet<IpAddr> = HashSet::new();
+        for exc in &self.exclude {
+            let ips: Vec<IpAddr> = parse_ip_or_range(exc)
+                .map_err(|e| format!("Error parsing exclusion '{exc}': {e}"))?;
+            if verbose && ips.len() > 1 {
+                eprintln!("Expanded '{exc}' to {} addresses (exclusion)", ips.len());
+            }
+            exclusions.extend(ips);
+        }
+
+        // Apply exclusions if needed
+        if !exclusions.is_empty() {
+            // let's see if we actually exclude anything
+            let excluded: usize = all_specs
+                .iter()
+                .filter(|s: &&TargetSpec| exclusions.contains(&s.addr))
+                .count();
+            if excluded == 0 {
+                if log {
+                    eprintln!("WARN: exclusions did not match any target addresses.");
+                }
+            } else if excluded == all_specs.len() {
+                return Err("All target addresses were excluded.".to_string());
+            } else {
+                if verbose {
+                    eprintln!("Excluding {excluded} addresses from target list");
+                }
+                all_specs.retain(|s: &TargetSpec| !exclusions.contains(&s.addr));
+            }
+        }
+
+        if all_specs.is_empty() {
+            return Err("No valid IP addresses provided.".to_string());
+        }
+        Ok(all_specs)
+    }
+
+    /// Effective interval and timeout for a target, taking per-target overrides into account.
+    ///
+    /// Timeout is limited to 4x the interval like the global one, see [MpConfig::parse].
+    pub fn timing(&self, opts: &TargetOpts) -> (Duration, Duration) {
+        let interval: Duration = opts.interval.unwrap_or(self.interval);
+        let timeout: Duration = opts.timeout.unwrap_or(self.timeout);
+        (interval, timeout.min(interval * 4))
+    }
+
+    /// Effective probe protocol for a target, taking per-target overrides into account.
+    pub fn proto(&self, opts: &TargetOpts) -> Proto {
+        opts.proto.unwrap_or(match (self.tcp, self.udp) {
+            (Some(port), _) => Proto::Tcp(port),
+            (_, Some(port)) => Proto::Udp(port),
+            _ => Proto::Icmp,
+        })
+    }
+
+    /// Effective outgoing IP options for a target, taking per-target overrides into account.
+    pub fn sock_opts(&self, opts: &TargetOpts) -> SockOpts {
+        opts.sock.or(&SockOpts {
+            ttl: self.ttl,
+            dscp: self.dscp,
+            df: self.df.then_some(true),
+        })
+    }
+
+    /// Effective status rules for a target, taking per-target overrides into account.
+    pub fn rules(&self, opts: &TargetOpts) -> StatusRules {
+        opts.rules.apply(&self.rules)
+    }
+
+    /// Address family preference for hostname resolution.
+    pub fn family(&self) -> AddrFamily {
+        match (self.ipv4, self.ipv6) {
+            (true, _) => AddrFamily::V4,
+            (_, true) => AddrFamily::V6,
+            _ => AddrFamily::Any,
+        }
+    }
 }
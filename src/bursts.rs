@@ -0,0 +1,184 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Loss-burst statistics: run lengths of consecutive lost probes over the whole
+//! run. Isolated drops and long bursts usually point to very different faults
+//! (eg. congestion or rate limiting vs. link flaps and route changes).
+
+use std::collections::HashMap;
+
+/// Lower bounds of the burst length histogram buckets
+pub(crate) const BURST_BUCKETS: [u32; 7] = [1, 2, 3, 5, 11, 21, 51];
+/// Out-of-order outcomes kept waiting for an earlier one before giving up on it
+const MAX_PENDING: usize = 256;
+
+/// Run-length tracker of consecutive losses.
+///
+/// Outcomes are processed in sequence number order, since a loss is only known
+/// after the timeout and thus often lands after the reply to a later probe.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LossBursts {
+    /// Next sequence number to process
+    next_seq: Option<u64>,
+    /// Outcomes (lost or not) waiting for earlier ones to land
+    pending: HashMap<u64, bool>,
+    /// Length of the ongoing burst
+    run: u32,
+    /// Number of finished bursts per length bucket, see [BURST_BUCKETS]
+    hist: [u64; BURST_BUCKETS.len()],
+    bursts: u64,
+    /// Probes lost in finished bursts
+    lost: u64,
+    max: u32,
+}
+
+impl LossBursts {
+    /// Record the outcome of probe `seq`.
+    pub fn record(&mut self, seq: u64, lost: bool) {
+        let next: u64 = *self.next_seq.get_or_insert(seq);
+        if seq != next {
+            self.pending.insert(seq, lost);
+            if self.pending.len() > MAX_PENDING {
+                // the expected outcome is not coming (eg. stats were reset): skip ahead
+                let skip: u64 = *self.pending.keys().min().unwrap();
+                self.next_seq = Some(skip);
+                self.drain();
+            }
+            return;
+        }
+        self.process(lost);
+        self.next_seq = Some(seq + 1);
+        self.drain();
+    }
+
+    /// Process pending outcomes which are now in order.
+    fn drain(&mut self) {
+        while let Some(seq) = self.next_seq {
+            match self.pending.remove(&seq) {
+                Some(lost) => {
+                    self.process(lost);
+                    self.next_seq = Some(seq + 1);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn process(&mut self, lost: bool) {
+        if lost {
+            self.run += 1;
+            return;
+        }
+        if self.run > 0 {
+            let bucket: usize = BURST_BUCKETS.partition_point(|b: &u32| *b <= self.run) - 1;
+            self.hist[bucket] += 1;
+            self.bursts += 1;
+            self.lost += self.run as u64;
+            self.max = self.max.max(self.run);
+            self.run = 0;
+        }
+    }
+
+    /// Forget everything.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Number of finished bursts.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.bursts
+    }
+
+    /// Length of the ongoing burst (0 if the last probe was answered).
+    #[inline]
+    pub fn current(&self) -> u32 {
+        self.run
+    }
+
+    /// Longest burst, including an ongoing one.
+    pub fn max(&self) -> u32 {
+        self.max.max(self.run)
+    }
+
+    /// Average length of finished bursts.
+    pub fn average(&self) -> Option<f64> {
+        match self.bursts {
+            0 => None,
+            n => Some(self.lost as f64 / n as f64),
+        }
+    }
+
+    /// Histogram of finished bursts as `(bucket label, count)`, eg. `("5-10", 2)`.
+    pub fn histogram(&self) -> Vec<(String, u64)> {
+        BURST_BUCKETS
+            .iter()
+            .enumerate()
+            .map(|(i, lo)| {
+                let label: String = match BURST_BUCKETS.get(i + 1) {
+                    Some(next) if next - 1 == *lo => lo.to_string(),
+                    Some(next) => format!("{lo}-{}", next - 1),
+                    None => format!("{lo}+"),
+                };
+                (label, self.hist[i])
+            })
+            .collect()
+    }
+
+    /// One-line summary, eg. `bursts: 3, max: 12, avg: 5.3, ongoing: 2`.
+    pub fn summary(&self) -> String {
+        let avg: String = self
+            .average()
+            .map_or("-".to_string(), |a| format!("{a:.1}"));
+        let mut s: String = format!("bursts: {}, max: {}, avg: {avg}", self.bursts, self.max());
+        if self.run > 0 {
+            s += &format!(", ongoing: {}", self.run);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss_bursts() {
+        let mut lb: LossBursts = LossBursts::default();
+        // ok, 1 lost, ok, 6 lost, ok ok, 12 lost, ok, 2 lost (ongoing)
+        let pattern: Vec<bool> = [
+            vec![false, true, false],
+            vec![true; 6],
+            vec![false; 2],
+            vec![true; 12],
+            vec![false],
+            vec![true; 2],
+        ]
+        .concat();
+        // deliver each loss two slots late, like timeouts landing after later replies
+        let mut order: Vec<usize> = (0..pattern.len()).collect();
+        order.sort_by_key(|i: &usize| i + 2 * pattern[*i] as usize);
+        for i in order {
+            lb.record(i as u64 + 65530, pattern[i]); // past the 16-bit wire value
+        }
+
+        assert_eq!((lb.count(), lb.current(), lb.max()), (3, 2, 12));
+        assert_eq!(lb.average(), Some(19.0 / 3.0));
+        let hist: Vec<(String, u64)> = lb.histogram();
+        assert_eq!(hist[0], ("1".to_string(), 1));
+        assert_eq!(hist[3], ("5-10".to_string(), 1));
+        assert_eq!(hist[4], ("11-20".to_string(), 1));
+        assert_eq!(hist[6], ("51+".to_string(), 0));
+        assert_eq!(lb.summary(), "bursts: 3, max: 12, avg: 6.3, ongoing: 2");
+
+        // a missing outcome is eventually skipped
+        let mut lb: LossBursts = LossBursts::default();
+        lb.record(0, true);
+        for seq in 2..=(MAX_PENDING as u64 + 2) {
+            lb.record(seq, seq % 2 == 0);
+        }
+        assert!(lb.pending.is_empty());
+        assert!(lb.count() > 100);
+    }
+}
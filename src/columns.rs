@@ -0,0 +1,209 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{structs::StatsSnapshot, timewin::StatWindow, tui::TableRow};
+use std::{fmt::Display, iter::once, str::FromStr};
+
+/// Statistics columns shown by default, in this order.
+pub(crate) const DEFAULT_COLUMNS: [Column; 8] = [
+    Column::Sent,
+    Column::Recv,
+    Column::Loss,
+    Column::Last,
+    Column::Mean,
+    Column::Min,
+    Column::Max,
+    Column::Stdev,
+];
+
+/// Extra statistics columns always included in the exit summary.
+pub(crate) const SUMMARY_COLUMNS: [Column; 3] = [Column::Jitter, Column::Avail, Column::Outages];
+
+/// Selectable statistics column of the target table.
+///
+/// Address (plus label/protocol) columns always come first and status last,
+/// the statistics columns in between are configurable with `--columns`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Column {
+    Sent,
+    Recv,
+    Loss,
+    Last,
+    Mean,
+    Min,
+    Max,
+    Stdev,
+    /// RFC 3550 interarrival jitter
+    Jitter,
+    /// Estimated mean opinion score of a VoIP call
+    Mos,
+    /// Estimated R-factor of a VoIP call
+    RFactor,
+    /// Late replies (after the timeout, within the grace period)
+    Late,
+    /// Duplicate replies
+    Dup,
+    /// Reordered replies
+    Reordered,
+    /// Reply TTL (and the typical one if it differs)
+    Ttl,
+    /// Availability based on outages
+    Avail,
+    /// Number of outages
+    Outages,
+    /// RTT percentile `(0.0 ..= 100.0]`
+    Pct(f64),
+}
+
+impl Column {
+    /// Whether this column follows the selected [StatWindow].
+    pub fn is_windowed(&self) -> bool {
+        matches!(
+            self,
+            Column::Sent | Column::Recv | Column::Late | Column::Loss | Column::Mean
+        )
+    }
+
+    /// Header text of this column, with the time window if one is selected.
+    pub fn header(&self, window: StatWindow) -> String {
+        match window {
+            StatWindow::All => self.to_string(),
+            w if self.is_windowed() => format!("{self} {w}"),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Formatted value of this column from a [StatsSnapshot].
+    pub fn cell(&self, snap: &StatsSnapshot) -> String {
+        match self {
+            Column::Sent => snap.sent.to_string(),
+            Column::Recv => snap.recv.to_string(),
+            Column::Loss => snap.loss_str(),
+            Column::Last => snap.last_str(),
+            Column::Mean => snap.mean_str(),
+            Column::Min => snap.min_str(),
+            Column::Max => snap.max_str(),
+            Column::Stdev => snap.stdev_str(),
+            Column::Jitter => snap.jitter_str(),
+            Column::Mos => snap.mos_str(),
+            Column::RFactor => snap.r_factor_str(),
+            Column::Late => snap.late.to_string(),
+            Column::Dup => snap.dups.to_string(),
+            Column::Reordered => snap.reordered.to_string(),
+            Column::Ttl => snap.ttl_str(),
+            Column::Avail => snap.outages.availability_str(),
+            Column::Outages => snap.outages.count.to_string(),
+            Column::Pct(p) => snap.percentile_str(*p),
+        }
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Column::Sent => write!(f, "Sent"),
+            Column::Recv => write!(f, "Recv"),
+            Column::Loss => write!(f, "Loss"),
+            Column::Last => write!(f, "Last"),
+            Column::Mean => write!(f, "Mean"),
+            Column::Min => write!(f, "Min"),
+            Column::Max => write!(f, "Max"),
+            Column::Stdev => write!(f, "Stdev"),
+            Column::Jitter => write!(f, "Jitter"),
+            Column::Mos => write!(f, "MOS"),
+            Column::RFactor => write!(f, "R"),
+            Column::Late => write!(f, "Late"),
+            Column::Dup => write!(f, "Dup"),
+            Column::Reordered => write!(f, "Reord"),
+            Column::Ttl => write!(f, "TTL"),
+            Column::Avail => write!(f, "Avail"),
+            Column::Outages => write!(f, "Outages"),
+            Column::Pct(p) => write!(f, "p{p}"),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    /// Parse a column name (case-insensitive), eg. `mean` or `p99.9`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s.trim().to_lowercase();
+        Ok(match name.as_str() {
+            "sent" => Column::Sent,
+            "recv" => Column::Recv,
+            "loss" => Column::Loss,
+            "last" => Column::Last,
+            "mean" => Column::Mean,
+            "min" => Column::Min,
+            "max" => Column::Max,
+            "stdev" => Column::Stdev,
+            "jitter" => Column::Jitter,
+            "mos" => Column::Mos,
+            "rfactor" | "r" => Column::RFactor,
+            "late" => Column::Late,
+            "dup" => Column::Dup,
+            "reord" | "reordered" => Column::Reordered,
+            "ttl" => Column::Ttl,
+            "avail" => Column::Avail,
+            "outages" => Column::Outages,
+            n => match n.strip_prefix('p').map(|p: &str| p.parse::<f64>()) {
+                Some(Ok(p)) if p > 0.0 && p <= 100.0 => Column::Pct(p),
+                _ => {
+                    return Err(format!(
+                        "Unknown column '{s}' (valid: sent, recv, loss, last, mean, min, max, stdev, jitter, mos, rfactor, late, dup, reord, ttl, avail, outages, pNN)"
+                    ));
+                }
+            },
+        })
+    }
+}
+
+/// Table header row for the given statistics columns and time window.
+pub(crate) fn header_row(columns: &[Column], window: StatWindow) -> TableRow {
+    TableRow::from_iter(
+        once("Address".to_string())
+            .chain(columns.iter().map(|c: &Column| c.header(window)))
+            .chain(once("Status".to_string())),
+    )
+}
+
+/// Percentiles needed for the given statistics columns.
+pub(crate) fn percentiles(columns: &[Column]) -> Vec<f64> {
+    columns
+        .iter()
+        .filter_map(|c: &Column| match c {
+            Column::Pct(p) => Some(*p),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_columns() {
+        assert_eq!("Mean".parse::<Column>(), Ok(Column::Mean));
+        assert_eq!("p99.9".parse::<Column>(), Ok(Column::Pct(99.9)));
+        assert_eq!("JITTER".parse::<Column>(), Ok(Column::Jitter));
+        assert_eq!(Column::Pct(50.0).to_string(), "p50");
+        assert_eq!(Column::Pct(99.9).to_string(), "p99.9");
+        assert!("p0".parse::<Column>().is_err());
+        assert!("p101".parse::<Column>().is_err());
+        assert!("median".parse::<Column>().is_err());
+
+        let cols: Vec<Column> = vec![Column::Sent, Column::Pct(50.0), Column::Pct(99.0)];
+        assert_eq!(percentiles(&cols), vec![50.0, 99.0]);
+        assert_eq!(
+            header_row(&cols, StatWindow::All).strings(),
+            ["Address", "Sent", "p50", "p99", "Status"]
+        );
+        assert_eq!(
+            header_row(&cols, StatWindow::M5).strings(),
+            ["Address", "Sent 5m", "p50", "p99", "Status"]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Failed probes by cause. A plain timeout, a probe which could not even be sent
+//! and a router reporting the destination unreachable all count as "loss", but
+//! they point to very different problems.
+
+use std::fmt::Display;
+
+/// Cause of a failed probe, see [ProbeError::category](crate::probe::ProbeError::category).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ErrorCategory {
+    /// No answer within the timeout
+    Timeout,
+    /// The probe could not be sent (eg. ENETUNREACH, EHOSTUNREACH, ENOBUFS)
+    SendFailed,
+    /// ICMP destination unreachable in response to the probe
+    Unreachable,
+    /// ICMP time exceeded (TTL expired in transit)
+    TtlExceeded,
+    /// Malformed or mismatched reply
+    BadReply,
+    /// Anything else
+    Other,
+}
+
+impl ErrorCategory {
+    /// All categories, in the order of their counters.
+    pub const ALL: [ErrorCategory; 6] = [
+        ErrorCategory::Timeout,
+        ErrorCategory::SendFailed,
+        ErrorCategory::Unreachable,
+        ErrorCategory::TtlExceeded,
+        ErrorCategory::BadReply,
+        ErrorCategory::Other,
+    ];
+
+    /// Abbreviation for the debug status column.
+    pub fn abbrev(&self) -> &'static str {
+        match self {
+            ErrorCategory::Timeout => "to",
+            ErrorCategory::SendFailed => "snd",
+            ErrorCategory::Unreachable => "unr",
+            ErrorCategory::TtlExceeded => "ttl",
+            ErrorCategory::BadReply => "bad",
+            ErrorCategory::Other => "oth",
+        }
+    }
+
+    #[inline]
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl Display for ErrorCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorCategory::Timeout => write!(f, "timeout"),
+            ErrorCategory::SendFailed => write!(f, "send failed"),
+            ErrorCategory::Unreachable => write!(f, "unreachable"),
+            ErrorCategory::TtlExceeded => write!(f, "TTL exceeded"),
+            ErrorCategory::BadReply => write!(f, "bad reply"),
+            ErrorCategory::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// Failed probe counters of a target, one per [ErrorCategory].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ErrorCounts([u64; ErrorCategory::ALL.len()]);
+
+impl ErrorCounts {
+    /// Count a failed probe.
+    #[inline]
+    pub fn record(&mut self, cat: ErrorCategory) {
+        self.0[cat.index()] += 1;
+    }
+
+    /// Uncount a failed probe, eg. a timeout which was answered late after all.
+    #[inline]
+    pub fn remove(&mut self, cat: ErrorCategory) {
+        self.0[cat.index()] = self.0[cat.index()].saturating_sub(1);
+    }
+
+    #[inline]
+    pub fn get(&self, cat: ErrorCategory) -> u64 {
+        self.0[cat.index()]
+    }
+
+    /// Failed probes in total.
+    pub fn total(&self) -> u64 {
+        self.0.iter().sum()
+    }
+
+    /// Categories with a non-zero count.
+    pub fn nonzero(&self) -> impl Iterator<Item = (ErrorCategory, u64)> + '_ {
+        ErrorCategory::ALL
+            .into_iter()
+            .map(|c: ErrorCategory| (c, self.get(c)))
+            .filter(|(_, n)| *n > 0)
+    }
+
+    /// Compact summary for the debug status column, eg. `to:5 snd:2`.
+    pub fn short(&self) -> String {
+        self.nonzero()
+            .map(|(c, n)| format!("{}:{n}", c.abbrev()))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// One-line summary, eg. `timeout: 5, send failed: 2`.
+    pub fn summary(&self) -> String {
+        match self.total() {
+            0 => "none".to_string(),
+            _ => self
+                .nonzero()
+                .map(|(c, n)| format!("{c}: {n}"))
+                .collect::<Vec<String>>()
+                .join(", "),
+        }
+    }
+
+    /// Forget everything.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_counts() {
+        let mut ec: ErrorCounts = ErrorCounts::default();
+        assert_eq!(
+            (ec.total(), ec.short(), ec.summary()),
+            (0, "".into(), "none".into())
+        );
+
+        for _ in 0..5 {
+            ec.record(ErrorCategory::Timeout);
+        }
+        ec.record(ErrorCategory::TtlExceeded);
+        ec.record(ErrorCategory::SendFailed);
+        assert_eq!(ec.get(ErrorCategory::Timeout), 5);
+        assert_eq!(ec.total(), 7);
+        assert_eq!(ec.short(), "to:5 snd:1 ttl:1");
+        assert_eq!(ec.summary(), "timeout: 5, send failed: 1, TTL exceeded: 1");
+
+        ec.clear();
+        assert_eq!(ec, ErrorCounts::default());
+    }
+}
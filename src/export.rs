@@ -0,0 +1,129 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Machine readable summary of a run (`--export FILE`), written as TOML on exit.
+//! One `[[target]]` table per target with its probe counts and loss-burst statistics.
+
+use crate::{bursts::LossBursts, structs::PingTarget};
+use serde::Serialize;
+use std::{fs, path::Path, sync::Arc};
+
+#[derive(Debug, Serialize)]
+struct Export {
+    target: Vec<TargetExport>,
+}
+
+#[derive(Debug, Serialize)]
+struct TargetExport {
+    name: String,
+    addr: String,
+    label: Option<String>,
+    group: Option<String>,
+    sent: u64,
+    recv: u64,
+    late: u64,
+    lost: u64,
+    bursts: BurstExport,
+}
+
+#[derive(Debug, Serialize)]
+struct BurstExport {
+    /// Finished bursts
+    count: u64,
+    /// Longest burst, including an ongoing one
+    max: u32,
+    /// Average length of finished bursts
+    average: Option<f64>,
+    /// Length of the ongoing burst, 0 if none
+    ongoing: u32,
+    histogram: Vec<BucketExport>,
+}
+
+#[derive(Debug, Serialize)]
+struct BucketExport {
+    /// Burst lengths of the bucket, eg. `5-10`
+    length: String,
+    count: u64,
+}
+
+impl From<&LossBursts> for BurstExport {
+    fn from(b: &LossBursts) -> Self {
+        Self {
+            count: b.count(),
+            max: b.max(),
+            average: b.average(),
+            ongoing: b.current(),
+            histogram: b
+                .histogram()
+                .into_iter()
+                .map(|(length, count)| BucketExport { length, count })
+                .collect(),
+        }
+    }
+}
+
+impl From<&Arc<PingTarget>> for TargetExport {
+    fn from(t: &Arc<PingTarget>) -> Self {
+        let data = t.data.read();
+        Self {
+            name: t.name(),
+            addr: t.addr().to_string(),
+            label: t.label.clone(),
+            group: t.group.clone(),
+            sent: data.sent,
+            recv: data.recv,
+            late: data.late,
+            lost: data.sent.saturating_sub(data.recv + data.late),
+            bursts: (&data.bursts).into(),
+        }
+    }
+}
+
+/// Summary of `targets` as a TOML document.
+pub(crate) fn export_toml(targets: &[Arc<PingTarget>]) -> Result<String, String> {
+    let export: Export = Export {
+        target: targets.iter().map(TargetExport::from).collect(),
+    };
+    toml::to_string(&export).map_err(|e| e.to_string())
+}
+
+/// Write the summary of `targets` to `path`.
+pub(crate) fn write_export(path: &Path, targets: &[Arc<PingTarget>]) -> Result<(), String> {
+    let content: String = export_toml(targets)?;
+    fs::write(path, content).map_err(|e| format!("Cannot write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let tgt: Arc<PingTarget> = Arc::new(PingTarget::new([192, 0, 2, 1].into(), 3600, 100));
+        {
+            let mut data = tgt.data.write();
+            // 10 probes: a single drop and a burst of 3
+            for (seq, lost) in [0, 1, 0, 0, 1, 1, 1, 0, 0, 0].into_iter().enumerate() {
+                data.bursts.record(seq as u64, lost == 1);
+            }
+            (data.sent, data.recv) = (10, 6);
+        }
+
+        let doc: toml::Table = export_toml(&[tgt]).unwrap().parse().unwrap();
+        let t: &toml::Value = &doc["target"][0];
+        assert_eq!(t["addr"].as_str(), Some("192.0.2.1"));
+        assert_eq!(t["lost"].as_integer(), Some(4));
+        assert!(t.get("label").is_none());
+        let b: &toml::Value = &t["bursts"];
+        assert_eq!(
+            (b["count"].as_integer(), b["max"].as_integer()),
+            (Some(2), Some(3))
+        );
+        assert_eq!(b["average"].as_float(), Some(2.0));
+        let hist: &toml::value::Array = b["histogram"].as_array().unwrap();
+        assert_eq!(hist[0]["length"].as_str(), Some("1"));
+        assert_eq!(hist[0]["count"].as_integer(), Some(1));
+        assert_eq!(hist[2]["count"].as_integer(), Some(1)); // 3-4
+    }
+}
@@ -0,0 +1,462 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! ICMP error replies (destination unreachable, time exceeded) to our echo
+//! requests. surge-ping only cares about echo replies, so a separate raw socket
+//! listens for the errors and matches them to probes by the echo request quoted
+//! in the error: destination, identifier and sequence number.
+//!
+//! The same sockets see the echo replies too, which are counted per probe to spot
+//! duplicates (surge-ping hands only the first one to the prober).
+
+use crate::errors::ErrorCategory;
+use parking_lot::Mutex;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io,
+    mem::MaybeUninit,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::io::unix::AsyncFd;
+
+/// Reports not claimed by a probe within this time are dropped
+const REPORT_TTL: Duration = Duration::from_secs(30);
+/// Unclaimed reports and echo reply counts are dropped at most this often
+const PRUNE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Type of an ICMP error reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IcmpErrorKind {
+    /// Destination unreachable (ICMPv4 type 3, ICMPv6 type 1)
+    Unreachable,
+    /// Time exceeded (ICMPv4 type 11, ICMPv6 type 3)
+    TimeExceeded,
+}
+
+/// An ICMP error reply to a probe, and the router which sent it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IcmpReport {
+    /// Source of the error, ie. the reporting router (or the target itself)
+    pub from: IpAddr,
+    pub kind: IcmpErrorKind,
+    pub code: u8,
+}
+
+impl IcmpReport {
+    /// ICMP type number of the report.
+    pub fn icmp_type(&self) -> u8 {
+        match (self.kind, self.from.is_ipv6()) {
+            (IcmpErrorKind::Unreachable, false) => 3,
+            (IcmpErrorKind::TimeExceeded, false) => 11,
+            (IcmpErrorKind::Unreachable, true) => 1,
+            (IcmpErrorKind::TimeExceeded, true) => 3,
+        }
+    }
+
+    /// Short description of the code, eg. `host` for "host unreachable".
+    pub fn code_str(&self) -> &'static str {
+        match (self.kind, self.from.is_ipv6(), self.code) {
+            (IcmpErrorKind::Unreachable, false, c) => match c {
+                0 | 6 | 11 => "net",
+                1 | 7 | 12 => "host",
+                2 => "protocol",
+                3 => "port",
+                4 => "frag needed",
+                5 => "source route",
+                9 | 10 | 13 => "prohibited",
+                _ => "other",
+            },
+            (IcmpErrorKind::Unreachable, true, c) => match c {
+                0 => "no route",
+                1 | 5 | 6 => "prohibited",
+                2 => "scope",
+                3 => "host",
+                4 => "port",
+                _ => "other",
+            },
+            (IcmpErrorKind::TimeExceeded, _, 1) => "reassembly",
+            (IcmpErrorKind::TimeExceeded, _, _) => "in transit",
+        }
+    }
+
+    /// Error category for the per-target counters.
+    pub fn category(&self) -> ErrorCategory {
+        match self.kind {
+            IcmpErrorKind::Unreachable => ErrorCategory::Unreachable,
+            IcmpErrorKind::TimeExceeded => ErrorCategory::TtlExceeded,
+        }
+    }
+}
+
+impl Display for IcmpReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            IcmpErrorKind::Unreachable => {
+                write!(f, "unreach ({}) from {}", self.code_str(), self.from)
+            }
+            IcmpErrorKind::TimeExceeded if self.code == 0 => {
+                write!(f, "TTL exceeded from {}", self.from)
+            }
+            IcmpErrorKind::TimeExceeded => {
+                write!(f, "time exceeded ({}) from {}", self.code_str(), self.from)
+            }
+        }
+    }
+}
+
+/// The echo request quoted in an ICMP error, identifying the probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct ProbeKey {
+    pub dest: IpAddr,
+    pub ident: u16,
+    pub seq: u16,
+}
+
+#[inline]
+fn be16(b: &[u8]) -> u16 {
+    u16::from_be_bytes([b[0], b[1]])
+}
+
+/// Parse an ICMPv4 error from a raw socket (IPv4 header included).
+pub(crate) fn parse_v4(buf: &[u8]) -> Option<(ProbeKey, IcmpReport)> {
+    let ihl: usize = (*buf.first()? & 0x0f) as usize * 4;
+    let src: [u8; 4] = buf.get(12..16)?.try_into().ok()?;
+    let icmp: &[u8] = buf.get(ihl..)?;
+    let kind: IcmpErrorKind = match *icmp.first()? {
+        3 => IcmpErrorKind::Unreachable,
+        11 => IcmpErrorKind::TimeExceeded,
+        _ => return None,
+    };
+    // quoted IPv4 header of the original datagram, which must be an ICMP echo request
+    let inner: &[u8] = icmp.get(8..)?;
+    let inner_ihl: usize = (*inner.first()? & 0x0f) as usize * 4;
+    if *inner.get(9)? != 1 {
+        return None;
+    }
+    let dest: [u8; 4] = inner.get(16..20)?.try_into().ok()?;
+    let echo: &[u8] = inner.get(inner_ihl..inner_ihl + 8)?;
+    if echo[0] != 8 {
+        return None;
+    }
+    let key: ProbeKey = ProbeKey {
+        dest: Ipv4Addr::from(dest).into(),
+        ident: be16(&echo[4..]),
+        seq: be16(&echo[6..]),
+    };
+    let from: IpAddr = Ipv4Addr::from(src).into();
+    Some((
+        key,
+        IcmpReport {
+            from,
+            kind,
+            code: icmp[1],
+        },
+    ))
+}
+
+/// Parse an ICMPv6 error from a raw socket (no IPv6 header) sent by `from`.
+/// Extension headers in the quoted packet are not supported.
+pub(crate) fn parse_v6(buf: &[u8], from: Ipv6Addr) -> Option<(ProbeKey, IcmpReport)> {
+    let kind: IcmpErrorKind = match *buf.first()? {
+        1 => IcmpErrorKind::Unreachable,
+        3 => IcmpErrorKind::TimeExceeded,
+        _ => return None,
+    };
+    let inner: &[u8] = buf.get(8..)?;
+    if *inner.get(6)? != 58 {
+        return None;
+    }
+    let dest: [u8; 16] = inner.get(24..40)?.try_into().ok()?;
+    let echo: &[u8] = inner.get(40..48)?;
+    if echo[0] != 128 {
+        return None;
+    }
+    let key: ProbeKey = ProbeKey {
+        dest: Ipv6Addr::from(dest).into(),
+        ident: be16(&echo[4..]),
+        seq: be16(&echo[6..]),
+    };
+    Some((
+        key,
+        IcmpReport {
+            from: from.into(),
+            kind,
+            code: buf[1],
+        },
+    ))
+}
+
+/// Parse an ICMPv4 echo reply from a raw socket (IPv4 header included) into the
+/// key of the probe it answers.
+pub(crate) fn parse_echo_v4(buf: &[u8]) -> Option<ProbeKey> {
+    let ihl: usize = (*buf.first()? & 0x0f) as usize * 4;
+    let src: [u8; 4] = buf.get(12..16)?.try_into().ok()?;
+    let echo: &[u8] = buf.get(ihl..ihl + 8)?;
+    (echo[0] == 0).then(|| ProbeKey {
+        dest: Ipv4Addr::from(src).into(),
+        ident: be16(&echo[4..]),
+        seq: be16(&echo[6..]),
+    })
+}
+
+/// Parse an ICMPv6 echo reply from a raw socket (no IPv6 header) sent by `from`.
+pub(crate) fn parse_echo_v6(buf: &[u8], from: Ipv6Addr) -> Option<ProbeKey> {
+    let echo: &[u8] = buf.get(..8)?;
+    (echo[0] == 129).then(|| ProbeKey {
+        dest: from.into(),
+        ident: be16(&echo[4..]),
+        seq: be16(&echo[6..]),
+    })
+}
+
+/* ---------------------------------------- */
+
+/// Error reports per probe, and when the stale ones were last dropped.
+#[derive(Debug, Default)]
+struct Reports {
+    reports: HashMap<ProbeKey, (IcmpReport, Instant)>,
+    pruned: Option<Instant>,
+}
+
+/// Echo replies seen per probe, and when the stale ones were last dropped.
+#[derive(Debug, Default)]
+struct EchoCounts {
+    counts: HashMap<ProbeKey, (u32, Instant)>,
+    pruned: Option<Instant>,
+}
+
+/// Listener for ICMP error replies on raw sockets (needs `CAP_NET_RAW`).
+///
+/// Reports wait here until the probe they belong to times out and claims them
+/// with [IcmpErrorListener::take].
+#[derive(Debug, Default)]
+pub(crate) struct IcmpErrorListener {
+    reports: Mutex<Reports>,
+    echoes: Mutex<EchoCounts>,
+}
+
+impl IcmpErrorListener {
+    /// Open raw ICMPv4 and ICMPv6 sockets and start listening. Fails only if
+    /// neither socket can be opened.
+    pub fn start() -> io::Result<Arc<Self>> {
+        let listener: Arc<Self> = Arc::new(Self::default());
+        let v4: io::Result<Socket> = raw_socket(Domain::IPV4, Protocol::ICMPV4);
+        let v6: io::Result<Socket> = raw_socket(Domain::IPV6, Protocol::ICMPV6);
+        if let (Err(e), Err(_)) = (&v4, &v6) {
+            return Err(io::Error::new(e.kind(), e.to_string()));
+        }
+        for (sock, v6) in [(v4, false), (v6, true)] {
+            if let Ok(sock) = sock {
+                tokio::spawn(listener.clone().listen(AsyncFd::new(sock)?, v6));
+            }
+        }
+        Ok(listener)
+    }
+
+    async fn listen(self: Arc<Self>, fd: AsyncFd<Socket>, v6: bool) {
+        let mut buf: [MaybeUninit<u8>; 1500] = [MaybeUninit::uninit(); 1500];
+        loop {
+            let Ok(mut guard) = fd.readable().await else {
+                return;
+            };
+            let (len, addr) = match guard.try_io(|s| s.get_ref().recv_from(&mut buf)) {
+                Ok(Ok(res)) => res,
+                Ok(Err(_)) | Err(_) => continue,
+            };
+            // SAFETY: recv_from() initialized the first `len` bytes
+            let data: &[u8] = unsafe { &*(&buf[..len] as *const [MaybeUninit<u8>] as *const [u8]) };
+            let (error, echo) = match (v6, addr.as_socket_ipv6()) {
+                (false, _) => (parse_v4(data), parse_echo_v4(data)),
+                (true, Some(a)) => (parse_v6(data, *a.ip()), parse_echo_v6(data, *a.ip())),
+                (true, None) => continue,
+            };
+            if let Some((key, report)) = error {
+                self.insert(key, report, Instant::now());
+            } else if let Some(key) = echo {
+                self.count_echo(key, Instant::now());
+            }
+        }
+    }
+
+    /// Store a report for the probe `key`, dropping stale unclaimed ones every now
+    /// and then.
+    pub fn insert(&self, key: ProbeKey, report: IcmpReport, now: Instant) {
+        let mut reports = self.reports.lock();
+        if reports
+            .pruned
+            .is_none_or(|t: Instant| now.duration_since(t) >= PRUNE_INTERVAL)
+        {
+            reports
+                .reports
+                .retain(|_, (_, t)| now.duration_since(*t) < REPORT_TTL);
+            reports.pruned = Some(now);
+        }
+        reports.reports.insert(key, (report, now));
+    }
+
+    /// Claim the report for the probe `key`, if one was received.
+    pub fn take(&self, key: &ProbeKey) -> Option<IcmpReport> {
+        self.reports.lock().reports.remove(key).map(|(r, _)| r)
+    }
+
+    /// Count an echo reply to the probe `key`, dropping stale unclaimed counts
+    /// every now and then.
+    pub fn count_echo(&self, key: ProbeKey, now: Instant) {
+        let mut echoes = self.echoes.lock();
+        if echoes
+            .pruned
+            .is_none_or(|t: Instant| now.duration_since(t) >= PRUNE_INTERVAL)
+        {
+            echoes
+                .counts
+                .retain(|_, (_, t)| now.duration_since(*t) < REPORT_TTL);
+            echoes.pruned = Some(now);
+        }
+        echoes.counts.entry(key).or_insert((0, now)).0 += 1;
+    }
+
+    /// Claim the number of echo replies received to the probe `key`.
+    pub fn take_echoes(&self, key: &ProbeKey) -> u32 {
+        self.echoes.lock().counts.remove(key).map_or(0, |(n, _)| n)
+    }
+}
+
+/// Non-blocking raw ICMP socket for the listener.
+fn raw_socket(domain: Domain, proto: Protocol) -> io::Result<Socket> {
+    let sock: Socket = Socket::new(domain, Type::RAW, Some(proto))?;
+    sock.set_nonblocking(true)?;
+    Ok(sock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_icmp_errors() {
+        // IPv4: host unreachable from 10.0.0.1, quoting an echo request to 192.0.2.7
+        let mut v4: Vec<u8> = vec![
+            0x45, 0, 0, 56, 0, 0, 0, 0, 64, 1, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+        ];
+        v4.extend([3, 1, 0, 0, 0, 0, 0, 0]);
+        v4.extend([
+            0x45, 0, 0, 28, 0, 0, 0, 0, 1, 1, 0, 0, 10, 0, 0, 2, 192, 0, 2, 7,
+        ]);
+        v4.extend([8, 0, 0, 0, 0x12, 0x34, 0, 42]);
+        let (key, report) = parse_v4(&v4).unwrap();
+        assert_eq!(
+            key,
+            ProbeKey {
+                dest: "192.0.2.7".parse().unwrap(),
+                ident: 0x1234,
+                seq: 42
+            }
+        );
+        assert_eq!(report.to_string(), "unreach (host) from 10.0.0.1");
+        assert_eq!(
+            (report.icmp_type(), report.category()),
+            (3, ErrorCategory::Unreachable)
+        );
+
+        // TTL exceeded, and an echo reply (not an error) is ignored
+        v4[20] = 11;
+        v4[21] = 0;
+        assert_eq!(
+            parse_v4(&v4).unwrap().1.to_string(),
+            "TTL exceeded from 10.0.0.1"
+        );
+        v4[20] = 0;
+        assert!(parse_v4(&v4).is_none());
+        assert!(parse_v4(&v4[..30]).is_none());
+
+        // IPv6: time exceeded quoting an echo request to 2001:db8::7
+        let router: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let dest: Ipv6Addr = "2001:db8::7".parse().unwrap();
+        let mut v6: Vec<u8> = vec![3, 0, 0, 0, 0, 0, 0, 0];
+        v6.extend([0x60, 0, 0, 0, 0, 8, 58, 1]);
+        v6.extend([0; 16]);
+        v6.extend(dest.octets());
+        v6.extend([128, 0, 0, 0, 0xab, 0xcd, 1, 0]);
+        let (key, report) = parse_v6(&v6, router).unwrap();
+        assert_eq!(
+            (key.dest, key.ident, key.seq),
+            (IpAddr::from(dest), 0xabcd, 256)
+        );
+        assert_eq!(report.category(), ErrorCategory::TtlExceeded);
+        assert_eq!(report.icmp_type(), 3);
+
+        // reports are claimed once, and stale ones expire
+        let listener: IcmpErrorListener = IcmpErrorListener::default();
+        let t0: Instant = Instant::now();
+        listener.insert(key, report, t0);
+        assert_eq!(listener.take(&key), Some(report));
+        assert_eq!(listener.take(&key), None);
+        listener.insert(key, report, t0);
+        let other: ProbeKey = ProbeKey { seq: 1, ..key };
+        listener.insert(other, report, t0 + REPORT_TTL);
+        assert_eq!(listener.take(&key), None);
+        assert_eq!(listener.take(&other), Some(report));
+
+        // ...but not on every report
+        let listener: IcmpErrorListener = IcmpErrorListener::default();
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+        let last: u64 = REPORT_TTL.as_secs() - 1;
+        listener.insert(key, report, t0);
+        listener.insert(other, report, at(last));
+        listener.insert(ProbeKey { seq: 2, ..key }, report, at(last + 1));
+        assert!(listener.reports.lock().reports.contains_key(&key));
+        listener.insert(
+            ProbeKey { seq: 3, ..key },
+            report,
+            at(last) + PRUNE_INTERVAL,
+        );
+        assert_eq!(listener.take(&key), None);
+        assert_eq!(listener.take(&other), Some(report));
+    }
+
+    #[test]
+    fn test_echo_counts() {
+        // IPv4 echo reply from 192.0.2.7, ident 0x1234, seq 42
+        let mut v4: Vec<u8> = vec![
+            0x45, 0, 0, 28, 0, 0, 0, 0, 64, 1, 0, 0, 192, 0, 2, 7, 10, 0, 0, 2,
+        ];
+        v4.extend([0, 0, 0, 0, 0x12, 0x34, 0, 42]);
+        let key: ProbeKey = parse_echo_v4(&v4).unwrap();
+        assert_eq!(
+            key,
+            ProbeKey {
+                dest: "192.0.2.7".parse().unwrap(),
+                ident: 0x1234,
+                seq: 42
+            }
+        );
+        assert!(parse_v4(&v4).is_none());
+        v4[20] = 8; // echo request
+        assert!(parse_echo_v4(&v4).is_none());
+
+        let from: Ipv6Addr = "2001:db8::7".parse().unwrap();
+        let v6: [u8; 8] = [129, 0, 0, 0, 0xab, 0xcd, 0, 1];
+        assert_eq!(
+            parse_echo_v6(&v6, from),
+            Some(ProbeKey {
+                dest: from.into(),
+                ident: 0xabcd,
+                seq: 1
+            })
+        );
+        assert!(parse_echo_v6(&v6[..4], from).is_none());
+
+        // a reply and its duplicate, claimed once
+        let listener: IcmpErrorListener = IcmpErrorListener::default();
+        let t0: Instant = Instant::now();
+        listener.count_echo(key, t0);
+        listener.count_echo(key, t0);
+        assert_eq!(listener.take_echoes(&key), 2);
+        assert_eq!(listener.take_echoes(&key), 0);
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! ICMP identifier allocation. Echo replies are matched to probes by identifier and
+//! sequence number, so each target needs an identifier of its own. Raw sockets see
+//! every echo reply of their address family, so there an identifier is unique across
+//! all sockets of the family (eg. ones with different DSCP markings).
+//!
+//! NOTE: with unprivileged ICMP datagram sockets the kernel replaces the identifier
+//! with the local "port" of the socket, and replies are told apart by address and
+//! sequence number only. So there a socket takes a single target per address (eg.
+//! `localhost` and `127.0.0.1` go to different sockets), and once all 65536
+//! identifiers of a socket are taken, the next targets get a new socket. Raw sockets
+//! see every echo reply, so a new one would not help there.
+
+use parking_lot::Mutex;
+use rand::random;
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use surge_ping::Client;
+
+/// Number of distinct ICMP identifiers
+const IDENTS: usize = 1 << 16;
+
+/// Identifiers in use on one datagram socket, or all raw sockets of an address
+/// family. One bit each.
+#[derive(Debug)]
+pub(crate) struct IdPool {
+    used: Box<[u64]>,
+    count: usize,
+    /// Number of leases per destination address
+    dests: HashMap<IpAddr, usize>,
+}
+
+impl Default for IdPool {
+    fn default() -> Self {
+        Self {
+            used: vec![0; IDENTS / 64].into(),
+            count: 0,
+            dests: HashMap::new(),
+        }
+    }
+}
+
+impl IdPool {
+    #[inline]
+    fn is_used(&self, id: u16) -> bool {
+        self.used[id as usize / 64] & (1 << (id % 64)) != 0
+    }
+
+    /// Allocate a free identifier, searching upwards from `hint` (wrapping around).
+    /// Returns `None` if all identifiers are taken.
+    pub fn alloc(&mut self, hint: u16) -> Option<u16> {
+        if self.is_full() {
+            return None;
+        }
+        let id: u16 = (0..=u16::MAX)
+            .map(|i: u16| hint.wrapping_add(i))
+            .find(|id: &u16| !self.is_used(*id))?;
+        self.used[id as usize / 64] |= 1 << (id % 64);
+        self.count += 1;
+        Some(id)
+    }
+
+    /// Return an identifier to the pool.
+    pub fn release(&mut self, id: u16) {
+        if self.is_used(id) {
+            self.used[id as usize / 64] &= !(1 << (id % 64));
+            self.count -= 1;
+        }
+    }
+
+    /// Number of identifiers in use.
+    #[inline]
+    pub fn in_use(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.count == IDENTS
+    }
+
+    /// Whether an identifier is leased for a target at `dest`.
+    #[inline]
+    pub fn has_dest(&self, dest: &IpAddr) -> bool {
+        self.dests.contains_key(dest)
+    }
+
+    /// Lease a free identifier from `pool` for a target at `dest`, starting from a
+    /// random one so that identifiers are not reused right away. With `per_dest` no
+    /// more than one lease per address is handed out. Returns `None` if there is
+    /// nothing to lease.
+    pub fn lease(pool: &Arc<Mutex<IdPool>>, dest: IpAddr, per_dest: bool) -> Option<IdLease> {
+        let mut ids = pool.lock();
+        if per_dest && ids.has_dest(&dest) {
+            return None;
+        }
+        let id: u16 = ids.alloc(random())?;
+        *ids.dests.entry(dest).or_default() += 1;
+        Some(IdLease {
+            pool: pool.clone(),
+            id,
+            dest,
+        })
+    }
+}
+
+/// An identifier leased from an [IdPool], returned to it when dropped.
+#[derive(Debug)]
+pub(crate) struct IdLease {
+    pool: Arc<Mutex<IdPool>>,
+    id: u16,
+    dest: IpAddr,
+}
+
+impl IdLease {
+    #[inline]
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+}
+
+impl Drop for IdLease {
+    fn drop(&mut self) {
+        let mut pool = self.pool.lock();
+        pool.release(self.id);
+        if let Some(n) = pool.dests.get_mut(&self.dest) {
+            *n -= 1;
+            if *n == 0 {
+                pool.dests.remove(&self.dest);
+            }
+        }
+    }
+}
+
+/// A [surge_ping::Client] (ie. socket) and the pool its identifiers are leased from.
+pub(crate) struct ClientShard {
+    pub client: Arc<Client>,
+    ids: Arc<Mutex<IdPool>>,
+}
+
+impl ClientShard {
+    /// Create a new [ClientShard] leasing identifiers from `ids`, which may be
+    /// shared with other shards.
+    pub fn new(client: Client, ids: Arc<Mutex<IdPool>>) -> Self {
+        Self {
+            client: Arc::new(client),
+            ids,
+        }
+    }
+
+    /// Whether all identifiers of the pool are in use.
+    pub fn is_full(&self) -> bool {
+        self.ids.lock().is_full()
+    }
+
+    /// Lease an identifier for a target at `dest`, see [IdPool::lease].
+    pub fn lease(&self, dest: IpAddr, per_dest: bool) -> Option<IdLease> {
+        IdPool::lease(&self.ids, dest, per_dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_pool() {
+        let mut pool: IdPool = IdPool::default();
+        assert_eq!(pool.alloc(7), Some(7));
+        assert_eq!(pool.alloc(7), Some(8));
+        assert_eq!(pool.alloc(u16::MAX), Some(u16::MAX));
+        assert_eq!(pool.alloc(u16::MAX), Some(0)); // wraps around
+        pool.release(8);
+        pool.release(8);
+        assert_eq!(pool.in_use(), 3);
+        assert_eq!(pool.alloc(7), Some(8));
+
+        // every identifier handed out once, then nothing left
+        let mut pool: IdPool = IdPool::default();
+        let mut seen: Vec<u16> = (0..IDENTS).map(|_| pool.alloc(random()).unwrap()).collect();
+        seen.sort_unstable();
+        seen.dedup();
+        assert_eq!(seen.len(), IDENTS);
+        assert!(pool.is_full());
+        assert_eq!(pool.alloc(0), None);
+
+        // a dropped lease frees its identifier
+        let pool: Arc<Mutex<IdPool>> = Arc::new(Mutex::new(pool));
+        pool.lock().release(42);
+        let lease: IdLease = IdPool::lease(&pool, [192, 0, 2, 1].into(), false).unwrap();
+        assert_eq!((lease.id(), pool.lock().is_full()), (42, true));
+        drop(lease);
+        assert_eq!(pool.lock().in_use(), IDENTS - 1);
+    }
+
+    #[test]
+    fn test_lease_per_dest() {
+        // eg. `localhost` and `127.0.0.1` on a datagram socket
+        let pool: Arc<Mutex<IdPool>> = Arc::default();
+        let dest: IpAddr = [127, 0, 0, 1].into();
+        let first: IdLease = IdPool::lease(&pool, dest, true).unwrap();
+        assert!(IdPool::lease(&pool, dest, true).is_none());
+        let other: IdLease = IdPool::lease(&pool, [127, 0, 0, 2].into(), true).unwrap();
+        assert_ne!(first.id(), other.id());
+
+        // raw sockets tell them apart by identifier
+        let second: IdLease = IdPool::lease(&pool, dest, false).unwrap();
+        drop(first);
+        assert!(pool.lock().has_dest(&dest));
+        drop(second);
+        assert!(!pool.lock().has_dest(&dest));
+        assert!(IdPool::lease(&pool, dest, true).is_some());
+    }
+}
@@ -3,7 +3,43 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use ipnet::IpNet;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs},
+};
+
+lazy_static! {
+    /// Regex to match RFC 1123 hostnames (dot-separated labels, optional trailing dot)
+    static ref HOSTNAME_RX: Regex =
+        Regex::new(r"^(?i)[a-z0-9]([a-z0-9-]{0,61}[a-z0-9])?(\.[a-z0-9]([a-z0-9-]{0,61}[a-z0-9])?)*\.?$")
+            .unwrap();
+}
+
+/// Address family preference for hostname resolution.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AddrFamily {
+    /// Keep the ordering of the system resolver
+    #[default]
+    Any,
+    /// Prefer IPv4 (A records)
+    V4,
+    /// Prefer IPv6 (AAAA records)
+    V6,
+}
+
+impl AddrFamily {
+    /// Whether the address belongs to the preferred family. Always true for [AddrFamily::Any].
+    #[inline]
+    pub fn matches(&self, addr: &IpAddr) -> bool {
+        match self {
+            AddrFamily::Any => true,
+            AddrFamily::V4 => addr.is_ipv4(),
+            AddrFamily::V6 => addr.is_ipv6(),
+        }
+    }
+}
 
 /// Parse an IP address, CIDR, or IP range from a string.
 /// Supported formats:
@@ -35,6 +71,36 @@ pub fn parse_ip_or_range(arg: &str) -> Result<Vec<IpAddr>, String> {
     Err(format!("Invalid IP address, CIDR, or range: {arg}"))
 }
 
+/// Whether the string looks like a hostname rather than an IP address or range.
+/// Purely numeric strings (eg. a mistyped `10.0.0.300`) are not considered hostnames.
+pub fn is_hostname(arg: &str) -> bool {
+    arg.len() <= 253
+        && arg.chars().any(|c: char| c.is_ascii_alphabetic())
+        && HOSTNAME_RX.is_match(arg)
+}
+
+/// Resolve a hostname into its A and/or AAAA addresses.
+///
+/// Uses the system resolver (`getaddrinfo()`), so `/etc/hosts` and any local
+/// stub resolver are consulted as usual. Addresses of the preferred family are
+/// sorted first, otherwise the resolver's ordering is kept. Duplicates are removed.
+pub fn resolve_host(name: &str, pref: AddrFamily) -> Result<Vec<IpAddr>, String> {
+    let mut seen: HashSet<IpAddr> = HashSet::new();
+    let mut addrs: Vec<IpAddr> = (name, 0u16)
+        .to_socket_addrs()
+        .map_err(|e| format!("Cannot resolve '{name}': {e}"))?
+        .map(|sa| sa.ip())
+        .filter(|ip: &IpAddr| seen.insert(*ip))
+        .collect();
+
+    if addrs.is_empty() {
+        return Err(format!("No addresses found for '{name}'"));
+    }
+    // stable sort -> preferred family first, resolver order otherwise preserved
+    addrs.sort_by_key(|ip: &IpAddr| !pref.matches(ip));
+    Ok(addrs)
+}
+
 /// Parse an IP range in the format:
 /// - 10.10.10.1-10 (short form, last octet only)
 /// - 10.10.10.1-10.10.10.10 (full form)
@@ -191,4 +257,23 @@ mod tests {
         assert_eq!(result[0], "::1".parse::<IpAddr>().unwrap());
         assert_eq!(result[4], "::5".parse::<IpAddr>().unwrap());
     }
+
+    #[test]
+    fn test_is_hostname() {
+        assert!(is_hostname("localhost"));
+        assert!(is_hostname("gw1.lab.example"));
+        assert!(is_hostname("gw-1.lab.example."));
+        assert!(!is_hostname("10.0.0.300"));
+        assert!(!is_hostname("10.0.0.1-10"));
+        assert!(!is_hostname("-bad.example"));
+        assert!(!is_hostname("under_score.example"));
+    }
+
+    #[test]
+    fn test_resolve_hosts_file() {
+        // "localhost" comes from /etc/hosts, so this works offline
+        let result: Vec<IpAddr> = resolve_host("localhost", AddrFamily::V4).unwrap();
+        assert_eq!(result[0], "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert!(result.iter().all(|ip| ip.is_loopback()));
+    }
 }
@@ -7,11 +7,21 @@
 //! The [`LatencyWindow`] provides O(1) amortized operations for tracking
 //! mean, min, max, variance, and standard deviation over a sliding window
 //! of samples. Should work for usual kinds of latency measurements.
+//!
+//! Percentiles come from a fixed-bucket log-linear histogram which is updated
+//! in O(1) per sample and scanned (a few hundred buckets) per query.
 
 use std::{cmp::max, collections::VecDeque};
 
 const MIN_WINDOW_SIZE: usize = 3;
 
+/// Histogram: each power of two is split into 2^4 = 16 sub-buckets (max. ~3% error)
+const HIST_SUB_BITS: u32 = 4;
+const HIST_SUBS: u32 = 1 << HIST_SUB_BITS;
+/// Histogram: values below this get a bucket of their own (exact)
+const HIST_LINEAR: u32 = 2 << HIST_SUB_BITS;
+const HIST_BUCKETS: usize = (HIST_LINEAR + (31 - HIST_SUB_BITS) * HIST_SUBS) as usize;
+
 /// O(1) amortized rolling latency window over the last N samples.
 ///
 /// Maintains a fixed-size sliding window of latency samples and computes
@@ -22,6 +32,11 @@ const MIN_WINDOW_SIZE: usize = 3;
 /// The window capacity is clamped to a minimum of 3 samples to ensure
 /// statistical operations are meaningful.
 ///
+/// ## Percentiles
+/// Samples are also counted in a log-linear histogram (allocated on first push),
+/// so [`LatencyWindow::percentiles`] costs O(buckets) regardless of the window size.
+/// Results are bucket midpoints clamped to the exact min/max, ie. within ~3%.
+///
 /// ## Numerical Considerations
 /// Variance is computed using the computational formula which is efficient
 /// but may lose precision for extremely large values or very small variance.
@@ -51,6 +66,7 @@ pub struct LatencyWindow {
     minq: VecDeque<(u32, usize)>,   // monotonic increasing (value, index)
     maxq: VecDeque<(u32, usize)>,   // monotonic decreasing (value, index)
     index: usize,                   // monotonically increasing sample index
+    hist: Vec<u32>,                 // sample counts per histogram bucket
 }
 
 impl LatencyWindow {
@@ -69,6 +85,7 @@ impl LatencyWindow {
             minq: VecDeque::new(),
             maxq: VecDeque::new(),
             index: 0,
+            hist: Vec::new(),
         }
     }
 
@@ -77,6 +94,10 @@ impl LatencyWindow {
         let idx: usize = self.index;
         self.index = self.index.wrapping_add(1);
         let val_f: f64 = val as f64;
+        if self.hist.is_empty() {
+            self.hist = vec![0; HIST_BUCKETS];
+        }
+        self.hist[hist_bucket(val)] += 1;
 
         if self.len < self.cap {
             // Growing
@@ -89,6 +110,7 @@ impl LatencyWindow {
             // Evict oldest at head
             let tail_pos: usize = self.head;
             let old: f64 = self.buf[tail_pos] as f64;
+            self.hist[hist_bucket(self.buf[tail_pos])] -= 1;
             self.buf[tail_pos] = val;
             self.head = (self.head + 1) % self.cap;
             self.sum += val_f - old;
@@ -177,6 +199,7 @@ impl LatencyWindow {
         self.minq.clear();
         self.maxq.clear();
         self.index = 0;
+        self.hist.fill(0);
     }
 
     #[inline]
@@ -266,6 +289,75 @@ impl LatencyWindow {
         let max: u32 = self.maxq.front().map(|(v, _)| *v).unwrap_or_default();
         Ok((mean, min, max))
     }
+
+    /// Approximate percentiles (nearest-rank) for each `p` in `(0.0 ..= 100.0]`.
+    /// The extremes (lowest and highest rank) are the exact min and max values.
+    ///
+    /// All requested percentiles are resolved with a single histogram scan.
+    pub fn percentiles(&self, ps: &[f64]) -> Result<Vec<u32>, String> {
+        self.no_samples_check()?;
+        if ps.iter().any(|p| !(*p > 0.0 && *p <= 100.0)) {
+            return Err("percentile out of range".into());
+        }
+        let (_, min, max) = self.mean_min_max()?;
+        let len: f64 = self.len as f64;
+
+        // (output position, rank) in ascending rank order
+        let mut ranks: Vec<(usize, u64)> = ps.iter().enumerate()
+            .map(|(i, p)| (i, (p / 100.0 * len).ceil().clamp(1.0, len) as u64))
+            .collect();
+        ranks.sort_unstable_by_key(|&(_, rank)| rank);
+
+        let mut out: Vec<u32> = vec![0; ps.len()];
+        let mut next: usize = 0;
+        let mut cumulative: u64 = 0;
+        for (bucket, &count) in self.hist.iter().enumerate() {
+            cumulative += count as u64;
+            while next < ranks.len() && ranks[next].1 <= cumulative {
+                let (pos, rank) = ranks[next];
+                // first and last rank are known exactly
+                out[pos] = match rank {
+                    1 => min,
+                    r if r == self.len as u64 => max,
+                    _ => hist_value(bucket).clamp(min, max),
+                };
+                next += 1;
+            }
+            if next == ranks.len() {
+                break;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Approximate single percentile, see [`LatencyWindow::percentiles`].
+    pub fn percentile(&self, p: f64) -> Result<u32, String> {
+        Ok(self.percentiles(&[p])?[0])
+    }
+}
+
+/// Histogram bucket for a value: exact below [HIST_LINEAR], then [HIST_SUBS]
+/// equally wide buckets per power of two.
+#[inline]
+fn hist_bucket(val: u32) -> usize {
+    if val < HIST_LINEAR {
+        return val as usize;
+    }
+    let exp: u32 = 31 - val.leading_zeros();    // > HIST_SUB_BITS
+    let sub: u32 = (val >> (exp - HIST_SUB_BITS)) & (HIST_SUBS - 1);
+    (HIST_LINEAR + (exp - HIST_SUB_BITS - 1) * HIST_SUBS + sub) as usize
+}
+
+/// Representative (midpoint) value of a histogram bucket.
+fn hist_value(bucket: usize) -> u32 {
+    let bucket: u32 = bucket as u32;
+    if bucket < HIST_LINEAR {
+        return bucket;
+    }
+    let rel: u32 = bucket - HIST_LINEAR;
+    let shift: u32 = rel / HIST_SUBS + 1;       // exponent - HIST_SUB_BITS
+    let low: u64 = ((HIST_SUBS + rel % HIST_SUBS) as u64) << shift;
+    (low + (1u64 << shift) / 2).min(u32::MAX as u64) as u32
 }
 
 /// Naive reference calculation for sum of squares, which here means
@@ -413,4 +505,49 @@ mod tests {
         assert!(lw.stdev().is_err());
         assert!(lw.mean_min_max().is_err());
     }
+
+    #[test]
+    fn test_percentiles() {
+        let mut lw: LatencyWindow = LatencyWindow::new(1000);
+        assert!(lw.percentile(50.0).is_err());
+
+        // small values are exact
+        for v in 1..=20 {
+            lw.push(v);
+        }
+        assert_eq!(lw.percentiles(&[50.0, 95.0, 100.0]).unwrap(), vec![10, 19, 20]);
+        assert!(lw.percentile(0.0).is_err());
+        assert!(lw.percentile(100.1).is_err());
+
+        // larger values are within the bucket precision, min/max exact
+        lw.clear();
+        for v in 1..=1000 {
+            lw.push(v * 100);
+        }
+        let pcts: Vec<u32> = lw.percentiles(&[99.0, 50.0, 90.0, 0.01, 100.0]).unwrap();
+        for (got, exp) in pcts.iter().zip([99_000.0, 50_000.0, 90_000.0, 100.0, 100_000.0]) {
+            assert!((*got as f64 - exp).abs() / exp <= 0.035, "got {got}, expected ~{exp}");
+        }
+        assert_eq!(pcts[3], 100);
+        assert_eq!(pcts[4], 100_000);
+
+        // evicted samples are no longer counted
+        let mut lw: LatencyWindow = LatencyWindow::new(100);
+        for _ in 0..100 {
+            lw.push(1_000_000);
+        }
+        for _ in 0..100 {
+            lw.push(500);
+        }
+        assert_eq!(lw.percentile(99.9).unwrap(), 500);
+
+        // bucket mapping is monotonic and round-trips within precision
+        let mut prev: usize = 0;
+        for v in (0..u32::MAX).step_by(9973) {
+            let b: usize = hist_bucket(v);
+            assert!(b >= prev && b < HIST_BUCKETS);
+            assert!((hist_value(b) as f64 - v as f64).abs() <= v as f64 * 0.035 + 1.0);
+            prev = b;
+        }
+    }
 }
@@ -5,105 +5,289 @@
 #![allow(dead_code)]
 
 mod args;
+mod bursts;
+mod columns;
+mod errors;
+mod export;
+mod icmperr;
+mod ident;
 mod ip_addresses;
 mod latencywin;
+mod outage;
+mod probe;
+mod rules;
+mod sim;
+mod sockopts;
+mod status;
 mod structs;
 mod tabulator;
+mod targets;
+mod timewin;
+mod ttl;
 mod tui;
 mod utils;
+mod voip;
 
 use crate::{
     args::MpConfig,
-    structs::{AppState, PacketRecord, PingStatus, PingTarget, StatsSnapshot},
+    bursts::LossBursts,
+    columns::{Column, SUMMARY_COLUMNS, percentiles},
+    errors::ErrorCategory,
+    export::write_export,
+    ip_addresses::{AddrFamily, resolve_host},
+    outage::{Outage, OutageStats},
+    probe::{IcmpProber, ProbeError, ProbeResult, Prober, Proto, TcpProber, UdpProber},
+    rules::{StatusRules, dwell_filter},
+    sockopts::SockOpts,
+    status::{StatusMachine, Transition},
+    structs::{
+        AppState, PacketRecord, PingStatus, PingTarget, PingTargetInner, StatsSnapshot, TargetCmd,
+        TargetLookup,
+    },
     tabulator::simple_tabulate,
-    tui::{TableRow, TerminalGuard, key_event_poll},
-    utils::setup_signal_handler,
+    targets::{TargetOpts, TargetSpec, expand_target},
+    timewin::StatWindow,
+    ttl::{ROUTE_CHANGE_HOLD, TtlChange},
+    tui::{Prompt, TableRow, TerminalGuard, key_event_poll},
+    utils::{
+        file_mtime, format_duration, local_time_str, setup_reload_handler, setup_signal_handler,
+    },
+    voip::{Codec, Quality},
 };
 
-use futures::future::join_all;
-use rand::{fill, random};
+use futures::{FutureExt, future::join_all};
+use rand::fill;
 use ratatui::{prelude::*, widgets::*};
 use std::{
+    collections::{HashMap, HashSet},
+    iter::once,
     net::IpAddr,
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
     },
-    time::Duration,
+    time::{Duration, SystemTime},
 };
-use surge_ping::{Client, IcmpPacket, PingIdentifier, PingSequence, Pinger, SurgeError};
 use tokio::time::{self, Instant, Interval};
 
 const DEFAULT_TICK: Duration = Duration::from_millis(200); // 5 Hz
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
 
 ////////////////////////////////////////////////////////////////////////////////
 
-/// Create [PingTarget] instances for each IP address.
-fn make_targets(addrs: &[IpAddr], histsize: usize, detailed: usize) -> Vec<Arc<PingTarget>> {
-    addrs
+/// Create [PingTarget] instances for each target specification.
+fn make_targets(specs: &[TargetSpec], histsize: usize, detailed: usize) -> Vec<Arc<PingTarget>> {
+    specs
         .iter()
-        .map(|addr| Arc::new(PingTarget::new(*addr, histsize, detailed)))
+        .map(|s| Arc::new(PingTarget::from_spec(s, histsize, detailed)))
         .collect()
 }
 
+/// Wall-clock send time of a probe sent at `sent`, for the outage log.
+fn sent_time(sent: std::time::Instant) -> SystemTime {
+    SystemTime::now()
+        .checked_sub(sent.elapsed())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
 /// Update ping statistics based on the result. Separated into fn for target lock granularity.
+///
+/// With `late_pending` the probe timed out but may still be answered during the grace
+/// period, so it's left out of the outage and loss-burst tracking until
+/// [update_late_stats] knows the final outcome.
 async fn update_ping_stats(
     tgt: &Arc<PingTarget>,
-    res: Result<(IcmpPacket, Duration), SurgeError>,
+    res: ProbeResult,
     mut rec: PacketRecord,
+    rules: &StatusRules,
+    outage_after: u32,
+    late_pending: bool,
 ) {
+    let sent_at: SystemTime = sent_time(rec.sent);
+    let now: std::time::Instant = std::time::Instant::now();
     let mut stats = tgt.data.write();
-    match res {
-        Ok((_, dur)) => {
+    let prev: PingStatus = std::mem::take(&mut stats.status);
+    if !late_pending {
+        stats.outages.record(sent_at, res.is_ok(), outage_after);
+        stats.bursts.record(rec.seq, res.is_err());
+    }
+    let reason: String = match res {
+        Ok(reply) => {
             stats.recv += 1;
-            stats.rtts.push(dur.as_micros() as u32);
-            stats.status = PingStatus::Ok;
-            rec.set_rtt(dur);
+            stats.check_reply_order(rec.seq);
+            let rtt: u32 = reply.rtt.as_micros() as u32;
+            stats.windows.record(rec.sent, Some(reply.rtt), now);
+            rec.set_rtt(reply.rtt);
+            if reply.closed {
+                // closed replies tell the host is up, but their RTTs are kept apart
+                rec.closed = true;
+                stats.recv_closed += 1;
+                stats.closed_rtts.push(rtt);
+                stats.status = PingStatus::Closed;
+                "port closed".to_string()
+            } else {
+                stats.rtts.push(rtt);
+                stats.update_jitter(rtt);
+                rec.ttl = reply.ttl;
+                let change: Option<TtlChange> =
+                    reply.ttl.and_then(|ttl: u8| stats.ttl.record(ttl, sent_at));
+                let (status, reason) = match change {
+                    Some(c) => (
+                        PingStatus::RouteChange,
+                        format!("reply TTL {} -> {}, probable route change", c.from, c.to),
+                    ),
+                    None if stats.ttl.recently_changed() => {
+                        (PingStatus::RouteChange, "reply".to_string())
+                    }
+                    None if matches!(prev, PingStatus::RouteChange) => (
+                        PingStatus::Ok,
+                        format!("reply TTL steady for {ROUTE_CHANGE_HOLD} replies"),
+                    ),
+                    None => (PingStatus::Ok, "reply".to_string()),
+                };
+                stats.status = status;
+                reason
+            }
         }
         Err(e) => {
-            stats.status = match e {
-                SurgeError::Timeout { .. } => {
-                    if stats.sent > 10 && stats.recv == 0 {
-                        PingStatus::NotReachable
-                    } else {
-                        PingStatus::Timeout
-                    }
+            stats.windows.record(rec.sent, None, now);
+            stats.errors.record(e.category());
+            if let ProbeError::IcmpError(r) = &e {
+                rec.icmp_error = Some(*r);
+                stats.icmp_error = Some((*r, SystemTime::now()));
+            }
+            let (status, reason) = match e {
+                ProbeError::Timeout if stats.sent > rules.unreachable && stats.recv == 0 => (
+                    PingStatus::NotReachable,
+                    format!("no replies to {} probes", stats.sent),
+                ),
+                ProbeError::Timeout => (
+                    PingStatus::Timeout,
+                    format!("no reply to probe #{}", rec.seq),
+                ),
+                e => {
+                    let reason: String = e.to_string();
+                    (PingStatus::Error(e), reason)
                 }
-                _ => PingStatus::Error(e),
             };
+            stats.status = status;
+            reason
         }
     };
     stats.recent.push(rec);
+    apply_status(tgt, &mut stats, prev, reason, rules, now);
+}
 
+/// Finish a status update: the status set from a probe outcome is overridden by the
+/// paused flag or the rules on recent history, and replaces `prev` subject to the
+/// dwell time.
+fn apply_status(
+    tgt: &PingTarget,
+    stats: &mut PingTargetInner,
+    prev: PingStatus,
+    mut reason: String,
+    rules: &StatusRules,
+    now: std::time::Instant,
+) {
     // Update "paused" status here if necessary, as it's the overriding status.
     // In theory the paused state could have been changed by the task spawned by ping_loop()
     // calling this function in the previous iteration before the flag toggle took effect.
     if tgt.is_paused() && !matches!(stats.status, PingStatus::Paused) {
         stats.status = PingStatus::Paused;
+        reason = "paused by user".to_string();
     }
 
     // Update status based on recent history if applicable
-    if matches!(stats.status, PingStatus::Ok | PingStatus::Timeout) {
-        if stats.is_flappy(10, 5) {
-            stats.status = PingStatus::Flappy
-        } else if stats.is_lossy(5, 0.5) {
-            stats.status = PingStatus::Lossy
-        } else if stats.is_laggy(10, 2.0).unwrap_or(false) {
-            stats.status = PingStatus::Laggy
+    if matches!(
+        stats.status,
+        PingStatus::Ok | PingStatus::Closed | PingStatus::Timeout | PingStatus::RouteChange
+    ) {
+        let ((fl_n, fl_t), (lo_n, lo_r), (la_n, la_f)) = (rules.flappy, rules.lossy, rules.laggy);
+        if stats.is_flappy(fl_n, fl_t) {
+            stats.status = PingStatus::Flappy;
+            let changes: usize = stats.recent.recent_transitions(fl_n);
+            reason = format!("{changes} up/down changes in last {fl_n} probes");
+        } else if stats.is_lossy(lo_n, lo_r) {
+            stats.status = PingStatus::Lossy;
+            let lost: usize = stats.recent.recent_losses(lo_n);
+            reason = format!("{lost} of last {lo_n} probes lost");
+        } else if stats.is_laggy(la_n, la_f).unwrap_or(false) {
+            stats.status = PingStatus::Laggy;
+            let recent: f64 = stats
+                .recent
+                .mean(Some(la_n))
+                .map_or(0.0, |d: Duration| d.as_secs_f64() * 1e3);
+            let overall: f64 = stats.rtts.mean().unwrap_or(0.0) / 1e3;
+            reason = format!(
+                "mean RTT of last {la_n} replies {recent:.1}ms > {la_f}x overall {overall:.1}ms"
+            );
         }
     }
+
+    // Hold the previous status until the new one has persisted for the dwell time
+    let next: PingStatus = std::mem::take(&mut stats.status);
+    let next: Option<PingStatus> = dwell_filter(&prev, &mut stats.pending, next, rules.dwell, now);
+    match next {
+        Some(next) => stats.set_status(next, reason, now),
+        None => stats.status = prev,
+    }
 }
 
-/// Set up a ping loop for each target.
+/// Update the stats of a timed out probe with its outcome at the end of the grace
+/// period: a late reply is no longer loss, and an ICMP error tells why there was
+/// no reply. Either one updates the status like an outcome within the timeout.
+fn update_late_stats(
+    tgt: &Arc<PingTarget>,
+    seq: u64,
+    sent: std::time::Instant,
+    res: ProbeResult,
+    rules: &StatusRules,
+    outage_after: u32,
+) {
+    let now: std::time::Instant = std::time::Instant::now();
+    let mut stats = tgt.data.write();
+    stats
+        .outages
+        .record(sent_time(sent), res.is_ok(), outage_after);
+    stats.bursts.record(seq, res.is_err());
+    let (status, reason) = match res {
+        Ok(reply) => {
+            stats.late += 1;
+            stats.errors.remove(ErrorCategory::Timeout);
+            stats.windows.record_late(sent, now);
+            if let Some(rec) = stats.recent.find_mut(seq) {
+                rec.set_rtt(reply.rtt);
+                rec.late = true;
+            }
+            (PingStatus::Ok, format!("late reply to probe #{seq}"))
+        }
+        Err(ProbeError::IcmpError(r)) => {
+            stats.errors.remove(ErrorCategory::Timeout);
+            stats.errors.record(r.category());
+            stats.icmp_error = Some((r, SystemTime::now()));
+            if let Some(rec) = stats.recent.find_mut(seq) {
+                rec.icmp_error = Some(r);
+            }
+            (PingStatus::Error(ProbeError::IcmpError(r)), r.to_string())
+        }
+        // still lost, as already shown at the timeout
+        Err(_) => return,
+    };
+    let prev: PingStatus = std::mem::replace(&mut stats.status, status);
+    apply_status(tgt, &mut stats, prev, reason, rules, now);
+}
+
+/// Set up a ping loop for each target, sending probes with `prober`.
 async fn ping_loop(
     tgt: Arc<PingTarget>,
-    client: Arc<Client>,
+    prober: Arc<dyn Prober>,
     quit: Arc<AtomicBool>,
     conf: Arc<MpConfig>,
     payload: Arc<[u8]>,
 ) {
-    let id: PingIdentifier = PingIdentifier(random());
-    let mut ticker: Interval = time::interval(conf.interval.min(DEFAULT_TICK));
+    let (interval, timeout) = conf.timing(&tgt.opts);
+    let rules: StatusRules = conf.rules(&tgt.opts);
+    let mut ticker: Interval = time::interval(interval.min(DEFAULT_TICK));
     let mut next_ping: Instant = tokio::time::Instant::now();
     let mut payload: Arc<[u8]> = match conf.randomize {
         // create a new payload for the ping loop which we can randomize
@@ -111,7 +295,7 @@ async fn ping_loop(
         false => payload.clone(),
     };
 
-    while !quit.load(Ordering::Relaxed) {
+    while !quit.load(Ordering::Relaxed) && !tgt.is_stopped() {
         ticker.tick().await;
         if tgt.is_paused() {
             // Adjust next ping time to not build a backlog while paused.
@@ -124,31 +308,22 @@ async fn ping_loop(
             continue;
         }
 
-        let seq: u16 = {
+        let seq: u64 = {
             let mut stats = tgt.data.write();
             // update sent count here to make sure it's incremented before
             // sending so that the main sent count stays accurate even if
             // ping fails or we get out of order replies etc
-            let sent: u64 = stats.sent;
+            let seq: u64 = stats.sent;
             stats.sent += 1;
-            // calculate the 16-bit sequence number from sent count,
-            // since 2^16 is the max for ICMP sequence numbers
-            let seq: u16 = (sent % 65536) as u16;
             // store last sent seq and timestamp for master reference
             stats.last_seq = seq;
             stats.last_sent = Some(std::time::Instant::now());
             seq
         };
+        // The sequence number is tracked as 64 bits so that it never wraps, only the
+        // on-wire value is cut to the 16 bits of an ICMP sequence number.
+        let wire_seq: u16 = seq as u16;
 
-        // The async ping task can be spawned either using a closure, or an
-        // async fn block. Both should be functionally equivalent.
-        // In either case the pinger is created anew for each async context.
-        //
-        // Function style (saved for reference):
-        // tokio::spawn(ping(client.clone(), tgt.clone(), conf.timeout, id, seq));
-        //
-        let mut pinger: Pinger = client.pinger(tgt.addr, id).await;
-        pinger.timeout(conf.timeout);
         let tgt_clone: Arc<PingTarget> = tgt.clone();
         let pl: Arc<[u8]> = match conf.randomize {
             true => {
@@ -164,50 +339,352 @@ async fn ping_loop(
             false => payload.clone(),
         };
 
+        // The async probe task can be spawned either using a closure, or an
+        // async fn block. Both should be functionally equivalent.
+        //
+        // Function style (saved for reference):
+        // tokio::spawn(ping(prober.clone(), tgt.clone(), conf.timeout, seq));
+        //
+        // The probe keeps waiting for a late reply during the grace period, but
+        // the stats are updated at the timeout as usual, and again with the final outcome
+        let grace: Duration = conf.late_grace;
+        let mut probe = prober.probe(tgt.addr(), wire_seq, pl, timeout + grace);
+        let outage_after: u32 = conf.outage_after;
+        let (addr, prober_clone) = (tgt.addr(), prober.clone());
+        let done: Instant = tokio::time::Instant::now() + timeout + grace;
         tokio::spawn(async move {
             let rec: PacketRecord = PacketRecord::new(seq);
-            let res = pinger.ping(PingSequence(seq), &pl).await;
-            update_ping_stats(&tgt_clone, res, rec).await;
+            let sent: std::time::Instant = rec.sent;
+            let res: ProbeResult = match grace.is_zero() {
+                true => (&mut probe).await,
+                false => time::timeout(timeout, &mut probe)
+                    .await
+                    .unwrap_or(Err(ProbeError::Timeout)),
+            };
+            let late_pending: bool = matches!(res, Err(ProbeError::Timeout)) && !grace.is_zero();
+            let replied: bool = res.is_ok();
+            update_ping_stats(&tgt_clone, res, rec, &rules, outage_after, late_pending).await;
+            if late_pending {
+                let res: ProbeResult = probe.await;
+                update_late_stats(&tgt_clone, seq, sent, res, &rules, outage_after);
+            }
+            if replied && prober_clone.sees_duplicates() {
+                // duplicates may trail the reply, so count them once the probe is over
+                time::sleep_until(done).await;
+                let dups: u32 = prober_clone.duplicates(addr, wire_seq);
+                tgt_clone.data.write().dups += dups as u64;
+            }
         });
 
-        next_ping += conf.interval;
+        next_ping += interval;
+    }
+}
+
+/// Periodically re-resolve a hostname target and switch it to a new address if needed.
+///
+/// The address family chosen at startup is kept, since the ping loop is tied to
+/// an IPv4 or IPv6 client. The current address is kept as long as the name still
+/// resolves to it, so round-robin DNS does not cause constant switching.
+async fn resolve_loop(tgt: Arc<PingTarget>, quit: Arc<AtomicBool>, conf: Arc<MpConfig>) {
+    let host: String = match &tgt.host {
+        Some(h) => h.clone(),
+        None => return,
+    };
+    let mut ticker: Interval = time::interval(DEFAULT_TICK);
+    let mut next_resolve: Instant = tokio::time::Instant::now() + conf.resolve;
+
+    while !quit.load(Ordering::Relaxed) && !tgt.is_stopped() {
+        ticker.tick().await;
+        if tokio::time::Instant::now() < next_resolve {
+            continue;
+        }
+        next_resolve += conf.resolve;
+
+        let (name, family) = (host.clone(), conf.family());
+        let lookup = tokio::task::spawn_blocking(move || resolve_host(&name, family));
+        let addrs: Vec<IpAddr> = match time::timeout(RESOLVE_TIMEOUT, lookup).await {
+            Ok(Ok(Ok(addrs))) => addrs,
+            // keep pinging the old address if the name can't be resolved right now
+            _ => continue,
+        };
+
+        let cur: IpAddr = tgt.addr();
+        if addrs.contains(&cur) {
+            continue;
+        }
+        if let Some(new) = addrs
+            .iter()
+            .find(|a: &&IpAddr| a.is_ipv4() == cur.is_ipv4())
+        {
+            tgt.set_addr(*new);
+        }
+    }
+}
+
+/// Spawn the ping loop (and the re-resolve loop for hostname targets) for a target.
+fn spawn_target(
+    app: &mut AppState,
+    tgt: &Arc<PingTarget>,
+    quit: &Arc<AtomicBool>,
+    conf: &Arc<MpConfig>,
+    payload: &Arc<[u8]>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let prober: Arc<dyn Prober> = match (&conf.sim, conf.proto(&tgt.opts)) {
+        (Some(sim), _) => Arc::new(sim.prober(&tgt.addr())),
+        (None, Proto::Icmp) => {
+            let (client, lease) = app.lease_ident(&tgt.addr(), conf.sock_opts(&tgt.opts))?;
+            Arc::new(IcmpProber::new(client, lease, app.icmp_errors.clone()))
+        }
+        (None, Proto::Tcp(port)) => Arc::new(TcpProber(port)),
+        (None, Proto::Udp(port)) => Arc::new(UdpProber(port)),
+    };
+    app.tasks.push(tokio::spawn(ping_loop(
+        tgt.clone(),
+        prober,
+        quit.clone(),
+        conf.clone(),
+        payload.clone(),
+    )));
+    if tgt.host.is_some() {
+        app.tasks.push(tokio::spawn(resolve_loop(
+            tgt.clone(),
+            quit.clone(),
+            conf.clone(),
+        )));
+    }
+    Ok(())
+}
+
+/// Re-read all targets and reconcile them with the currently running ones.
+///
+/// Targets still present with identical options keep running with their statistics
+/// intact. Removed and changed targets are stopped, and new or changed ones are started.
+/// The current target set is kept as-is if the targets can't be loaded.
+async fn reload_targets(
+    app: &mut AppState<'_>,
+    quit: &Arc<AtomicBool>,
+    conf: &Arc<MpConfig>,
+    payload: &Arc<[u8]>,
+) {
+    // Parsing may involve DNS lookups -> keep them off the async worker threads
+    let c: Arc<MpConfig> = conf.clone();
+    let mut specs: Vec<TargetSpec> =
+        match tokio::task::spawn_blocking(move || c.load_specs(false)).await {
+            Ok(Ok(specs)) => specs,
+            Ok(Err(e)) => return app.notify(format!("Reload failed: {e}")),
+            Err(e) => return app.notify(format!("Reload failed: {e}")),
+        };
+
+    // Honor interactive additions and removals
+    specs.extend(app.added.iter().cloned());
+    let mut seen: HashSet<String> = HashSet::new();
+    specs.retain(|s: &TargetSpec| !app.removed.contains(&s.key()) && seen.insert(s.key()));
+
+    let mut current: HashMap<String, Arc<PingTarget>> =
+        app.targets.drain(..).map(|t| (t.key(), t)).collect();
+    let (mut added, mut removed) = (0usize, 0usize);
+    let mut errors: Vec<String> = vec![];
+
+    for spec in &specs {
+        match current.remove(&spec.key()) {
+            Some(tgt) if tgt.matches(spec) => app.targets.push(tgt),
+            old => {
+                if let Some(tgt) = old {
+                    tgt.stop();
+                    removed += 1;
+                }
+                let tgt: Arc<PingTarget> = Arc::new(PingTarget::from_spec(
+                    spec,
+                    conf.histsize as usize,
+                    conf.detailed as usize,
+                ));
+                match spawn_target(app, &tgt, quit, conf, payload) {
+                    Ok(_) => {
+                        app.targets.push(tgt);
+                        added += 1;
+                    }
+                    Err(e) => errors.push(e.to_string()),
+                }
+            }
+        }
+    }
+    for tgt in current.values() {
+        tgt.stop();
+        removed += 1;
+    }
+    app.tasks.retain(|h| !h.is_finished());
+    app.clamp_selection();
+
+    match errors.first() {
+        Some(e) => app.notify(format!("Reloaded with {} error(s): {e}", errors.len())),
+        None => app.notify(format!(
+            "Targets reloaded: {added} added, {removed} removed"
+        )),
+    }
+}
+
+/// Expand a target typed in the prompt. Hostname lookups are kept off the async
+/// worker threads and given up after [RESOLVE_TIMEOUT].
+async fn lookup_target(
+    txt: String,
+    family: AddrFamily,
+) -> (String, Result<Vec<TargetSpec>, String>) {
+    let t: String = txt.clone();
+    let lookup = tokio::task::spawn_blocking(move || expand_target(&t, family));
+    let res: Result<Vec<TargetSpec>, String> = match time::timeout(RESOLVE_TIMEOUT, lookup).await {
+        Ok(Ok(res)) => res,
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("lookup timed out".to_string()),
+    };
+    (txt, res)
+}
+
+/// Carry out the target additions and removals requested from the TUI.
+///
+/// Additions are looked up in the background and applied once done, so a slow
+/// resolver doesn't hold up the UI. A failed one reopens the prompt with the error.
+fn apply_target_cmds(
+    app: &mut AppState<'_>,
+    quit: &Arc<AtomicBool>,
+    conf: &Arc<MpConfig>,
+    payload: &Arc<[u8]>,
+) {
+    for cmd in std::mem::take(&mut app.pending) {
+        match cmd {
+            TargetCmd::Add(txt) => {
+                let lookup: TargetLookup = tokio::spawn(lookup_target(txt, app.family));
+                app.lookups.push(lookup);
+            }
+            TargetCmd::Remove(tgt) => {
+                tgt.stop();
+                app.targets.retain(|t| !Arc::ptr_eq(t, &tgt));
+                app.added.retain(|s| s.key() != tgt.key());
+                app.removed.insert(tgt.key());
+                app.notify(format!("Removed {}", tgt.name()));
+            }
+        }
+    }
+
+    let (done, running): (Vec<TargetLookup>, Vec<TargetLookup>) = std::mem::take(&mut app.lookups)
+        .into_iter()
+        .partition(|h| h.is_finished());
+    app.lookups = running;
+    for lookup in done {
+        match lookup.now_or_never() {
+            Some(Ok((_, Ok(specs)))) => add_targets(app, &specs, quit, conf, payload),
+            // keep the input so it can be fixed, unless another prompt is open by now
+            Some(Ok((txt, Err(e)))) => match app.prompt {
+                None => app.prompt = Some(Prompt::AddTarget(txt, Some(e))),
+                Some(_) => app.notify(format!("Cannot add {txt}: {e}")),
+            },
+            Some(Err(e)) => app.notify(format!("Cannot add target: {e}")),
+            None => {}
+        }
+    }
+    app.tasks.retain(|h| !h.is_finished());
+    app.clamp_selection();
+}
+
+/// Start monitoring the targets `specs` not already monitored.
+fn add_targets(
+    app: &mut AppState<'_>,
+    specs: &[TargetSpec],
+    quit: &Arc<AtomicBool>,
+    conf: &Arc<MpConfig>,
+    payload: &Arc<[u8]>,
+) {
+    let existing: HashSet<String> = app.targets.iter().map(|t| t.key()).collect();
+    let mut added: usize = 0;
+    for spec in specs.iter().filter(|s| !existing.contains(&s.key())) {
+        let tgt: Arc<PingTarget> = Arc::new(PingTarget::from_spec(
+            spec,
+            conf.histsize as usize,
+            conf.detailed as usize,
+        ));
+        if let Err(e) = spawn_target(app, &tgt, quit, conf, payload) {
+            app.notify(format!("Cannot add {}: {e}", spec.addr));
+            break;
+        }
+        app.targets.push(tgt);
+        app.added.push(spec.clone());
+        app.removed.remove(&spec.key());
+        added += 1;
+    }
+    if added > 0 {
+        app.notify(format!("Added {added} target(s)"));
+    } else if app.status_message().is_none() {
+        app.notify("Nothing to add, target(s) already monitored");
     }
 }
 
 /// Extract statistics data from a target's inner data.
-async fn extract_stats(tgt: &Arc<PingTarget>, to: Duration) -> (StatsSnapshot, String) {
+async fn extract_stats(
+    tgt: &Arc<PingTarget>,
+    to: Duration,
+    pcts: &[f64],
+    codec: Codec,
+    window: StatWindow,
+) -> (StatsSnapshot, String) {
     // Holding the lock inside this function only should minimize contention.
     // Do all the expensive string formatting in the caller.
     let stats = tgt.data.read();
-    let snap: StatsSnapshot = StatsSnapshot::new_from(&stats, to);
+    let snap: StatsSnapshot = StatsSnapshot::new_from(&stats, to, pcts, codec, window);
     // status formatting is cheap relative to float formatting
     (snap, format!("{}", stats.status))
 }
 
+/// Probe protocol column text, with the outgoing IP options of ICMP probes if any
+/// (eg. `icmp ef df`), so that differently marked probes to one host can be told apart.
+fn proto_str(conf: &MpConfig, opts: &TargetOpts) -> String {
+    let proto: Proto = conf.proto(opts);
+    let sock: SockOpts = conf.sock_opts(opts);
+    match proto == Proto::Icmp && !sock.is_empty() {
+        true => format!("{proto} {sock}"),
+        false => proto.to_string(),
+    }
+}
+
 /// Format a single target's data into a [TableRow]. Separate fn for ease of parallelization.
-async fn format_row(t: &Arc<PingTarget>, snap: StatsSnapshot, s: String, debug: bool) -> TableRow {
+async fn format_row(
+    t: &Arc<PingTarget>,
+    snap: StatsSnapshot,
+    s: String,
+    app: &AppState<'_>,
+    conf: &MpConfig,
+) -> TableRow {
+    let debug: bool = app.debug;
     let status: String = if debug {
-        match &snap.error {
+        let status: String = match &snap.error {
             Some(e) => e.to_string(),
             None => s,
+        };
+        match snap.errors.total() {
+            0 => status,
+            _ => format!("{status} [{}]", snap.errors.short()),
         }
     } else {
         s
     };
 
+    // Hostname targets are flagged if their address has changed since startup
+    let addr: String = match (&t.host, debug) {
+        (Some(h), true) => format!("{h} ({})", t.addr()),
+        (Some(h), false) if t.addr_changes() > 0 => format!("{h} *"),
+        _ => t.name(),
+    };
+
     // Do all the (expensive) string formatting after releasing the lock.
-    let mut row: TableRow = TableRow::from_iter([
-        t.addr.to_string(),
-        snap.sent.to_string(),
-        snap.recv.to_string(),
-        snap.loss_str(),
-        snap.last_str(),
-        snap.mean_str(),
-        snap.min_str(),
-        snap.max_str(),
-        snap.stdev_str(),
-        status,
-    ]);
+    let mut row: TableRow = TableRow::from_iter(
+        once(addr)
+            .chain(app.columns.iter().map(|c: &Column| c.cell(&snap)))
+            .chain(once(status)),
+    );
+    if app.labels {
+        row.insert_item(1, t.label.as_deref().unwrap_or(""));
+    }
+    if app.protos {
+        row.insert_item(1, proto_str(conf, &t.opts));
+    }
     if debug {
         row.add_item(snap.latest_seq.to_string());
     }
@@ -229,31 +706,174 @@ async fn format_row(t: &Arc<PingTarget>, snap: StatsSnapshot, s: String, debug:
             PingStatus::Lossy => {
                 row.set_style_all(Style::new().light_yellow());
             }
+            PingStatus::Closed => {
+                row.set_style_all(Style::new().cyan());
+            }
             PingStatus::Laggy | PingStatus::Flappy => {
                 row.set_style_all(Style::new().yellow());
             }
+            PingStatus::RouteChange => {
+                row.set_style_all(Style::new().light_blue());
+            }
             _ => {}
         }
     }
+    if t.addr_changes() > 0 {
+        row.set_style(0, Style::new().bold());
+    }
+
+    // Colour-code call quality estimates
+    if let Some(voip) = snap.voip
+        && !t.is_paused()
+    {
+        let style: Style = match voip.quality() {
+            Quality::Good => Style::new().green(),
+            Quality::Fair => Style::new().yellow(),
+            Quality::Poor => Style::new().light_red(),
+            Quality::Bad => Style::new().red().bold(),
+        };
+        let offset: usize = 1 + app.labels as usize + app.protos as usize;
+        for (i, c) in app.columns.iter().enumerate() {
+            if matches!(c, Column::Mos | Column::RFactor) {
+                row.set_style(offset + i, style);
+            }
+        }
+    }
     row
 }
 
 /// Gather current data from all targets.
-async fn gather_target_data(tgts: &[Arc<PingTarget>], debug: bool, to: Duration) -> Vec<TableRow> {
+async fn gather_target_data(app: &AppState<'_>, conf: &MpConfig) -> Vec<TableRow> {
+    let tgts: &[Arc<PingTarget>] = &app.targets;
+    let pcts: Vec<f64> = percentiles(&app.columns);
     // Collect all extract_stats futures and run them concurrently, then process results
-    let res = join_all(tgts.iter().map(|t| extract_stats(t, to))).await;
+    let res = join_all(
+        tgts.iter()
+            .map(|t| extract_stats(t, conf.timing(&t.opts).1, &pcts, conf.codec, app.window)),
+    )
+    .await;
 
     // Ditto for formatting rows
     let data: Vec<TableRow> = join_all(
         tgts.iter()
             .zip(res.into_iter())
-            .map(|(t, (snap, s))| format_row(t, snap, s, debug)),
+            .map(|(t, (snap, s))| format_row(t, snap, s, app, conf)),
     )
     .await;
 
     data
 }
 
+/// Details of a target for the info pane: availability and outage statistics, ICMP
+/// errors and failed probes by cause, late/duplicate/reordered replies, reply TTL
+/// changes, loss bursts, time spent in each status, and the outage and status logs.
+fn info_lines(tgt: Option<&Arc<PingTarget>>) -> Vec<Line<'static>> {
+    let Some(tgt) = tgt else {
+        return vec![Line::from("Select a target for details").dim()];
+    };
+    let now: SystemTime = SystemTime::now();
+    let data = tgt.data.read();
+    let st: OutageStats = data.outages.stats(now);
+    let dur = |d: Option<Duration>| d.map_or("-".to_string(), format_duration);
+
+    let mut lines: Vec<Line> = vec![
+        Line::from(tgt.name()).bold(),
+        Line::from(format!("Availability: {}", st.availability_str())),
+        Line::from(format!("Outages:      {}", st.count)),
+        Line::from(format!("Longest:      {}", dur(st.longest))),
+        Line::from(format!("MTBF:         {}", dur(st.mtbf))),
+        Line::from(format!("MTTR:         {}", dur(st.mttr))),
+    ];
+    if let Some(o) = data.outages.current() {
+        lines
+            .push(Line::from(format!("DOWN for {}", format_duration(o.duration(now)))).light_red());
+    }
+
+    lines.push(Line::from(""));
+    if let Some((r, at)) = &data.icmp_error {
+        lines.push(Line::from(format!("Last ICMP error: {r}")));
+        lines.push(Line::from(format!("  at {}", local_time_str(*at))));
+    }
+    lines.push(Line::from(format!(
+        "Failed probes: {}",
+        data.errors.total()
+    )));
+    lines.extend(
+        data.errors
+            .nonzero()
+            .map(|(c, n)| Line::from(format!("  {:<13} {n}", c.to_string()))),
+    );
+    for (what, n) in [
+        ("Late", data.late),
+        ("Duplicate", data.dups),
+        ("Reordered", data.reordered),
+    ] {
+        if n > 0 {
+            lines.push(Line::from(format!("{what} replies: {n}")));
+        }
+    }
+    if data.ttl.current().is_some() {
+        lines.push(Line::from(format!("Reply TTL: {}", data.ttl.summary())));
+        lines.extend(
+            data.ttl
+                .log()
+                .iter()
+                .rev()
+                .map(|c: &TtlChange| Line::from(format!("  {c}"))),
+        );
+    }
+
+    let bursts: &LossBursts = &data.bursts;
+    lines.push(Line::from(""));
+    lines.push(Line::from(format!("Loss bursts:  {}", bursts.count())));
+    lines.push(Line::from(format!("Max burst:    {}", bursts.max())));
+    lines.push(Line::from(format!(
+        "Avg burst:    {}",
+        bursts
+            .average()
+            .map_or("-".to_string(), |a: f64| format!("{a:.1}"))
+    )));
+    lines.extend(
+        bursts
+            .histogram()
+            .into_iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(len, n)| Line::from(format!("  {len:>5} lost: {n}"))),
+    );
+
+    let machine: &StatusMachine = &data.machine;
+    lines.push(Line::from(""));
+    lines.push(Line::from(format!("Status changes: {}", machine.changes())));
+    lines.extend(
+        machine
+            .time_in_state(std::time::Instant::now())
+            .into_iter()
+            .map(|(k, d)| Line::from(format!("  {k:<8} {}", format_duration(d)))),
+    );
+
+    if !data.outages.log().is_empty() {
+        lines.push(Line::from(""));
+        lines.extend(
+            data.outages
+                .log()
+                .iter()
+                .rev()
+                .map(|o: &Outage| Line::from(o.to_string())),
+        );
+    }
+    if !machine.log().is_empty() {
+        lines.push(Line::from(""));
+        lines.extend(
+            machine
+                .log()
+                .iter()
+                .rev()
+                .map(|t: &Transition| Line::from(t.to_string())),
+        );
+    }
+    lines
+}
+
 /// Render the current frame. Display will be updated as soon as this function completes.
 fn render_frame(frame: &mut Frame, state: &AppState, data: &[TableRow]) {
     let layout = &mut state.layout.write();
@@ -272,17 +892,45 @@ fn render_frame(frame: &mut Frame, state: &AppState, data: &[TableRow]) {
     .row_highlight_style(Style::new().reversed())
     .column_highlight_style(Style::new().bg(Color::Indexed(240)));
 
-    let procinfo = Paragraph::new(format!(
-        "CPU: {:>7} | mem: {} | pid: {}",
+    let procinfo: String = format!(
+        "{}CPU: {:>7} | mem: {} | pid: {}",
+        if state.unprivileged {
+            "unprivileged | "
+        } else {
+            ""
+        },
         state.pi.cpu_str(),
         state.pi.mem_str(),
         state.pi.pid,
-    ))
-    .alignment(Alignment::Right);
+    );
+    // Status bar: optional message on the left, process info on the right
+    let status = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Length(procinfo.len() as u16),
+    ])
+    .split(layout.status);
+
+    // Details of the selected target, if any
+    let selected: Option<&Arc<PingTarget>> = layout
+        .tablestate
+        .selected()
+        .and_then(|i: usize| state.targets.get(i));
+    let info: Paragraph = Paragraph::new(info_lines(selected))
+        .wrap(Wrap { trim: false })
+        .block(Block::bordered().title(" Details "));
 
     frame.render_widget(&state.title, layout.title);
     frame.render_stateful_widget(table, layout.table, &mut layout.tablestate);
-    frame.render_widget(procinfo, layout.status);
+    frame.render_widget(info, layout.info);
+    frame.render_widget(
+        Paragraph::new(procinfo).alignment(Alignment::Right),
+        status[1],
+    );
+    if let Some(prompt) = &state.prompt {
+        frame.render_widget(Paragraph::new(prompt.to_string()).reversed(), status[0]);
+    } else if let Some(msg) = state.status_message() {
+        frame.render_widget(Paragraph::new(msg).bold(), status[0]);
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -291,37 +939,33 @@ fn render_frame(frame: &mut Frame, state: &AppState, data: &[TableRow]) {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let conf: Arc<MpConfig> = MpConfig::parse().into();
 
-    let title = Line::from(format!("Multi-pinger v{}", conf.ver));
+    let title = Line::from(match conf.sim {
+        Some(_) => format!("Multi-pinger v{} (SIMULATION)", conf.ver),
+        None => format!("Multi-pinger v{}", conf.ver),
+    });
     let mut app: AppState<'static> = AppState {
-        targets: make_targets(&conf.addrs, conf.histsize as usize, conf.detailed as usize),
+        targets: make_targets(&conf.specs, conf.histsize as usize, conf.detailed as usize),
         title: Some(title.centered().style(Style::new().bold().on_green())),
         ..Default::default()
     }
     .build(&conf)?;
 
-    // Setup table header style and cache widths. Layout is locked for this block only.
-    {
-        app.headers.set_style_all(Style::new().bold().yellow());
-        let mut layout = app.layout.write();
-        layout.tbl_hdr_widths = app.headers.widths();
-        layout.tbl_colspacing = 2;
-    }
+    // Header style and widths were set up by build()
+    app.layout.write().tbl_colspacing = 2;
 
     // Spawn ping tasks
     let payload: Arc<[u8]> = vec![0u8; conf.size as usize].into();
     let quit: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
-    for tgt in &app.targets {
-        let client = match tgt.addr {
-            IpAddr::V4(_) => app.c_v4.as_ref().expect("IPv4 client missing"),
-            IpAddr::V6(_) => app.c_v6.as_ref().expect("IPv6 client missing"),
-        };
-        app.tasks.push(tokio::spawn(ping_loop(
-            tgt.clone(),
-            client.clone(),
-            quit.clone(),
-            conf.clone(),
-            payload.clone(),
-        )));
+    for tgt in app.targets.clone() {
+        spawn_target(&mut app, &tgt, &quit, &conf, &payload)?;
+    }
+
+    // Targets file can be reloaded on change or on SIGHUP
+    let reload: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let mut mtime: Option<SystemTime> = None;
+    if let Some(path) = &conf.file {
+        mtime = file_mtime(path);
+        setup_reload_handler(reload.clone());
     }
 
     // Full-console TUI initialization - the RAII guard will clean up on drop
@@ -333,7 +977,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     while !quit.load(Ordering::Relaxed) {
         // If no keypress event -> wait for next tick.
         // We also want to redraw only on UI interval, or when a keypress is handled.
-        let keypress_event: bool = key_event_poll(5, &quit, &app)?;
+        let keypress_event: bool = key_event_poll(5, &quit, &mut app)?;
         if !keypress_event {
             tick.tick().await;
             if tokio::time::Instant::now() < app.ui_next_refresh {
@@ -341,8 +985,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
+        // Reload targets if requested, or if the targets file has been modified
+        if let Some(path) = &conf.file {
+            let cur: Option<SystemTime> = file_mtime(path);
+            if cur != mtime {
+                mtime = cur;
+                reload.store(true, Ordering::Relaxed);
+            }
+        }
+        if reload.swap(false, Ordering::Relaxed) {
+            reload_targets(&mut app, &quit, &conf, &payload).await;
+        }
+        apply_target_cmds(&mut app, &quit, &conf, &payload);
+
         // Gather data for display and render the frame
-        let data: Vec<TableRow> = gather_target_data(&app.targets, app.debug, conf.timeout).await;
+        let data: Vec<TableRow> = gather_target_data(&app, &conf).await;
         guard
             .term
             .draw(|frame: &mut Frame| render_frame(frame, &app, &data))?;
@@ -358,14 +1015,527 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if app.debug {
         eprintln!("Main thread quitting. Waiting for tasks to terminate...");
     }
-    join_all(app.tasks).await;
+    join_all(app.tasks.drain(..)).await;
 
     // Print final stats
+    app.add_columns(&SUMMARY_COLUMNS);
     for line in simple_tabulate(
-        &gather_target_data(&app.targets, app.debug, conf.timeout).await,
+        &gather_target_data(&app, &conf).await,
         Some(&app.headers.strings()),
     ) {
         println!("{line}");
     }
+    // ... and outages, failures, loss bursts and status changes of targets which had any
+    let now: SystemTime = SystemTime::now();
+    for t in app.targets.iter() {
+        let data = t.data.read();
+        if data.outages.log().is_empty()
+            && data.bursts.max() == 0
+            && data.errors.total() == 0
+            && data.late == 0
+            && data.dups == 0
+            && data.reordered == 0
+            && data.ttl.changes() == 0
+            && data.machine.changes() <= 1
+        {
+            continue;
+        }
+        let hist: Vec<String> = data
+            .bursts
+            .histogram()
+            .into_iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(len, n)| format!("{len}: {n}"))
+            .collect();
+        println!("\n{}:", t.name());
+        println!("  {}", data.outages.stats(now).summary());
+        println!("  failed probes: {}", data.errors.summary());
+        println!(
+            "  replies: {} late, {} duplicate, {} reordered",
+            data.late, data.dups, data.reordered
+        );
+        if data.ttl.current().is_some() {
+            println!("  reply TTL: {}", data.ttl.summary());
+        }
+        for c in data.ttl.log() {
+            println!("    {c}");
+        }
+        println!("  loss {} [{}]", data.bursts.summary(), hist.join(", "));
+        println!(
+            "  status: {}",
+            data.machine.summary(std::time::Instant::now())
+        );
+        for o in data.outages.log() {
+            println!("    {o}");
+        }
+    }
+    if let Some(path) = &conf.export
+        && let Err(e) = write_export(path, &app.targets)
+    {
+        eprintln!("Export failed: {e}");
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        icmperr::{IcmpErrorKind, IcmpReport},
+        probe::{MockProber, MockStep},
+        sim::Simulation,
+        status::StatusKind,
+    };
+    use std::io::ErrorKind;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    /// Run a ping loop against a [MockProber] for `pings` probes (1s interval, 2s timeout)
+    /// and wait for all of them to complete. Tokio time must be paused.
+    async fn run_mock(script: Vec<MockStep>, pings: u32) -> Arc<PingTarget> {
+        run_mock_with(MpConfig::default(), script, pings).await
+    }
+
+    /// [run_mock] with a 1s interval and 2s timeout on top of `conf`.
+    async fn run_mock_with(conf: MpConfig, script: Vec<MockStep>, pings: u32) -> Arc<PingTarget> {
+        let tgt: Arc<PingTarget> = Arc::new(PingTarget::new([192, 0, 2, 1].into(), 3600, 100));
+        run_mock_on(tgt, conf, script, pings).await
+    }
+
+    /// [run_mock_with] on an existing target.
+    async fn run_mock_on(
+        tgt: Arc<PingTarget>,
+        conf: MpConfig,
+        script: Vec<MockStep>,
+        pings: u32,
+    ) -> Arc<PingTarget> {
+        let conf: Arc<MpConfig> = Arc::new(MpConfig {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(2),
+            outage_after: 3,
+            ..conf
+        });
+        let prober: Arc<dyn Prober> = Arc::new(MockProber::new(script));
+        let quit: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+        let payload: Arc<[u8]> = vec![0u8; 32].into();
+
+        // Probes go out at 0.2s, 1.2s, 2.2s... Stop between two probes, since the
+        // loop notices the stop flag only on its next tick.
+        let handle = tokio::spawn(ping_loop(tgt.clone(), prober, quit, conf.clone(), payload));
+        time::sleep(conf.interval * pings - 500 * MS).await;
+        tgt.stop();
+        handle.await.unwrap();
+        time::sleep(conf.timeout + conf.late_grace).await;
+        tgt
+    }
+
+    fn snapshot(tgt: &PingTarget) -> StatsSnapshot {
+        let pcts: &[f64] = &[50.0, 100.0];
+        StatsSnapshot::new_from(
+            &tgt.data.read(),
+            Duration::from_secs(2),
+            pcts,
+            Codec::G711,
+            StatWindow::All,
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_replies() {
+        let tgt: Arc<PingTarget> = run_mock(vec![MockStep::Reply(10 * MS)], 20).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::Ok));
+
+        let snap: StatsSnapshot = snapshot(&tgt);
+        assert_eq!((snap.sent, snap.recv), (20, 20));
+        assert_eq!(snap.loss_str(), "0.0%");
+        assert_eq!(snap.mean_str(), "10.00");
+        assert_eq!(
+            (snap.min, snap.max, snap.last),
+            (Some(10000), Some(10000), Some(10000))
+        );
+        assert_eq!(snap.stdev, Some(0.0));
+        assert_eq!(snap.jitter, Some(0.0));
+        assert_eq!(snap.voip.map(|v| v.quality()), Some(Quality::Good));
+        assert_eq!(snap.percentile_str(50.0), "10.00");
+        assert_eq!(snap.hist.recent_losses, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_jitter() {
+        // RTT alternates 10/20ms -> |D| = 10ms every time, J converges towards it
+        let script: Vec<MockStep> = vec![MockStep::Reply(10 * MS), MockStep::Reply(20 * MS)];
+        let tgt: Arc<PingTarget> = run_mock(script, 20).await;
+        let expected: f64 = 10_000.0 * (1.0 - (15.0_f64 / 16.0).powi(19));
+        let jitter: f64 = snapshot(&tgt).jitter.unwrap();
+        assert!((jitter - expected).abs() < 1e-6, "{jitter} != {expected}");
+
+        // a single reply gives no jitter yet, nor does a reset target
+        let tgt: Arc<PingTarget> = run_mock(vec![MockStep::Reply(10 * MS)], 1).await;
+        assert_eq!(snapshot(&tgt).jitter_str(), "-");
+        let tgt: Arc<PingTarget> = run_mock(vec![MockStep::Reply(10 * MS)], 3).await;
+        tgt.reset_stats();
+        assert_eq!(snapshot(&tgt).jitter, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_status_classification() {
+        use MockStep::{Loss, Reply};
+        let r: MockStep = Reply(10 * MS);
+
+        // every other probe lost -> flapping
+        let tgt: Arc<PingTarget> = run_mock(vec![r, Loss], 20).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::Flappy));
+        assert_eq!(snapshot(&tgt).loss_str(), "50.0%");
+        assert_eq!(snapshot(&tgt).outages.count, 0); // never 3 losses in a row
+        let bursts: LossBursts = tgt.data.read().bursts.clone();
+        assert_eq!((bursts.count(), bursts.current()), (9, 1)); // last one still ongoing
+
+        // steady replies, then a run of losses -> lossy (but not flapping)
+        let script: Vec<MockStep> = [[r; 5], [Loss; 5]].concat();
+        let tgt: Arc<PingTarget> = run_mock(script, 10).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::Lossy));
+        assert_eq!(tgt.data.read().bursts.current(), 5);
+        let log: Vec<(StatusKind, StatusKind, String)> = tgt
+            .data
+            .read()
+            .machine
+            .log()
+            .iter()
+            .map(|t: &Transition| (t.from, t.to, t.reason.clone()))
+            .collect();
+        assert_eq!(
+            log,
+            vec![
+                (StatusKind::None, StatusKind::Ok, "reply".to_string()),
+                (
+                    StatusKind::Ok,
+                    StatusKind::Timeout,
+                    "no reply to probe #5".to_string()
+                ),
+                (
+                    StatusKind::Timeout,
+                    StatusKind::Lossy,
+                    "3 of last 5 probes lost".to_string()
+                ),
+            ]
+        );
+
+        // latency jumps to 10x -> laggy
+        let script: Vec<MockStep> = [vec![r; 20], vec![Reply(100 * MS); 10]].concat();
+        let tgt: Arc<PingTarget> = run_mock(script, 30).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::Laggy));
+        let reason: String = tgt.data.read().machine.log().back().unwrap().reason.clone();
+        assert!(
+            reason.starts_with("mean RTT of last 10 replies"),
+            "{reason}"
+        );
+
+        // never any reply -> unreachable
+        let tgt: Arc<PingTarget> = run_mock(vec![Loss], 12).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::NotReachable));
+        assert_eq!(snapshot(&tgt).mean, None);
+        assert_eq!(snapshot(&tgt).mos_str(), "-");
+        let outages: OutageStats = snapshot(&tgt).outages;
+        assert_eq!((outages.count, outages.ongoing), (1, true));
+        assert_eq!(
+            tgt.data.read().outages.current().map(|o| o.losses),
+            Some(12)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_route_change() {
+        use MockStep::ReplyTtl;
+        // the reply TTL drops by two hops after 10 probes
+        let script: Vec<MockStep> = [
+            vec![ReplyTtl(10 * MS, 57); 10],
+            vec![ReplyTtl(10 * MS, 55); 12],
+        ]
+        .concat();
+        let tgt: Arc<PingTarget> = run_mock(script.clone(), 15).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::RouteChange));
+        let snap: StatsSnapshot = snapshot(&tgt);
+        assert_eq!(snap.ttl_str(), "55 (57)");
+        {
+            let data = tgt.data.read();
+            let last: &Transition = data.machine.log().back().unwrap();
+            assert_eq!(
+                (last.to, last.reason.as_str()),
+                (
+                    StatusKind::RouteChange,
+                    "reply TTL 57 -> 55, probable route change"
+                )
+            );
+            assert_eq!(data.recent.last().unwrap().ttl, Some(55));
+        }
+
+        // back to OK once the new TTL has held for a while
+        let tgt: Arc<PingTarget> = run_mock(script, 22).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::Ok));
+        assert_eq!(tgt.data.read().ttl.changes(), 1);
+        assert_eq!(snapshot(&tgt).ttl_str(), "55");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_closed_not_laggy() {
+        // slow "port closed" replies after fast actual ones are neither jitter nor lag
+        let script: Vec<MockStep> = [
+            vec![MockStep::Reply(10 * MS); 10],
+            vec![MockStep::Closed(900 * MS); 10],
+        ]
+        .concat();
+        let tgt: Arc<PingTarget> = run_mock(script, 20).await;
+        let data = tgt.data.read();
+        assert!(matches!(data.status, PingStatus::Closed));
+        assert_eq!(data.jitter, Some(0.0));
+        assert_eq!(
+            data.is_laggy(10, 2.0),
+            Err("No valid RTTs to calculate mean".into())
+        );
+        assert!(
+            data.recent
+                .iter()
+                .filter(|r| r.closed)
+                .all(|r| r.has_response())
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_closed_and_errors() {
+        let tgt: Arc<PingTarget> = run_mock(vec![MockStep::Closed(5 * MS)], 5).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::Closed));
+        let snap: StatsSnapshot = snapshot(&tgt);
+        assert_eq!((snap.recv, snap.recv_closed), (5, 5));
+        assert_eq!(snap.mean_str(), "5.00"); // no actual replies -> closed RTTs shown
+
+        let tgt: Arc<PingTarget> =
+            run_mock(vec![MockStep::Error(ErrorKind::HostUnreachable)], 3).await;
+        assert!(matches!(tgt.data.read().status, PingStatus::Error(_)));
+        let snap: StatsSnapshot = snapshot(&tgt);
+        assert_eq!(snap.recv, 0);
+        assert!(snap.error.is_some());
+        assert_eq!(snap.errors.get(ErrorCategory::SendFailed), 3);
+        assert_eq!(snap.errors.total(), 3);
+
+        // TTL exceeded from a router on the way
+        let report: IcmpReport = IcmpReport {
+            from: "10.0.0.1".parse().unwrap(),
+            kind: IcmpErrorKind::TimeExceeded,
+            code: 0,
+        };
+        let tgt: Arc<PingTarget> = run_mock(vec![MockStep::IcmpError(report, 5 * MS)], 3).await;
+        let (snap, status) = extract_stats(
+            &tgt,
+            Duration::from_secs(2),
+            &[],
+            Codec::G711,
+            StatWindow::All,
+        )
+        .await;
+        assert_eq!(status, "TTL exceeded from 10.0.0.1");
+        assert_eq!(snap.errors.get(ErrorCategory::TtlExceeded), 3);
+        let data = tgt.data.read();
+        assert_eq!(data.icmp_error.map(|(r, _)| r), Some(report));
+        assert!(data.recent.iter().all(|r| r.icmp_error == Some(report)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_late_replies() {
+        use MockStep::Reply;
+        let script: Vec<MockStep> = vec![Reply(10 * MS), Reply(2500 * MS), Reply(10 * MS)];
+
+        // without a grace period the slow reply is lost
+        let tgt: Arc<PingTarget> = run_mock(script.clone(), 3).await;
+        let snap: StatsSnapshot = snapshot(&tgt);
+        assert_eq!((snap.recv, snap.late, snap.lost()), (2, 0, 1));
+
+        let conf: MpConfig = MpConfig {
+            late_grace: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let tgt: Arc<PingTarget> = run_mock_with(conf, script, 3).await;
+        let snap: StatsSnapshot = snapshot(&tgt);
+        assert_eq!((snap.sent, snap.recv, snap.late), (3, 2, 1));
+        assert_eq!(snap.loss(), 0.0);
+        assert_eq!(snap.errors.total(), 0);
+        assert_eq!(snap.max, Some(10_000)); // late RTTs stay out of the RTT stats
+        let data = tgt.data.read();
+        let rec: &PacketRecord = data.recent.iter().find(|r| r.seq == 1).unwrap();
+        assert!(rec.late);
+        assert_eq!(rec.rtt(), Ok(2500 * MS));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_late_outcomes() {
+        use MockStep::{Loss, Reply};
+        let conf = || MpConfig {
+            late_grace: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        // ICMP errors are only reported at the end of the grace period
+        let report: IcmpReport = IcmpReport {
+            from: "192.0.2.254".parse().unwrap(),
+            kind: IcmpErrorKind::Unreachable,
+            code: 1,
+        };
+        let tgt: Arc<PingTarget> =
+            run_mock_with(conf(), vec![MockStep::IcmpError(report, 5 * MS)], 4).await;
+        let (snap, status) = extract_stats(
+            &tgt,
+            Duration::from_secs(2),
+            &[],
+            Codec::G711,
+            StatWindow::All,
+        )
+        .await;
+        assert_eq!(status, "unreach (host) from 192.0.2.254");
+        assert_eq!(snap.errors.get(ErrorCategory::Unreachable), 4);
+        assert_eq!(snap.errors.get(ErrorCategory::Timeout), 0);
+        {
+            let data = tgt.data.read();
+            assert_eq!(data.machine.state(), StatusKind::Error);
+            assert_eq!(data.bursts.current(), 4);
+            assert!(data.outages.current().is_some());
+        }
+
+        // a late reply ends the loss burst and shows the target as up again
+        let script: Vec<MockStep> = vec![Reply(10 * MS), Loss, Reply(2500 * MS)];
+        let tgt: Arc<PingTarget> = run_mock_with(conf(), script, 3).await;
+        let data = tgt.data.read();
+        assert!(matches!(data.status, PingStatus::Ok));
+        assert_eq!((data.bursts.count(), data.bursts.current()), (1, 0));
+        assert!(data.outages.current().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reload_targets() {
+        let conf = |name: &str, count: u32| -> Arc<MpConfig> {
+            let profile: String =
+                format!("seed = 1\n[[target]]\nname = \"{name}\"\ncount = {count}");
+            Arc::new(MpConfig {
+                sim: Some(Arc::new(Simulation::from_toml(&profile).unwrap())),
+                ..MpConfig::default()
+            })
+        };
+        let quit: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+        let payload: Arc<[u8]> = Arc::from(vec![0u8; 32]);
+        let mut app: AppState = AppState::default();
+
+        reload_targets(&mut app, &quit, &conf("web", 3), &payload).await;
+        assert_eq!(app.targets.len(), 3);
+        assert_eq!(
+            app.status_message(),
+            Some("Targets reloaded: 3 added, 0 removed")
+        );
+        let kept: Vec<Arc<PingTarget>> = app.targets.clone();
+        app.layout.get_mut().tablestate.select(Some(2));
+
+        // last target removed, the others keep running untouched
+        reload_targets(&mut app, &quit, &conf("web", 2), &payload).await;
+        assert_eq!(app.targets.len(), 2);
+        assert!(
+            app.targets
+                .iter()
+                .zip(&kept)
+                .all(|(a, b)| Arc::ptr_eq(a, b))
+        );
+        assert!(kept[2].is_stopped() && !kept[0].is_stopped());
+        assert_eq!(app.layout.get_mut().tablestate.selected(), Some(1));
+
+        // same keys with new labels -> restarted
+        reload_targets(&mut app, &quit, &conf("db", 2), &payload).await;
+        assert_eq!(
+            app.status_message(),
+            Some("Targets reloaded: 2 added, 2 removed")
+        );
+        assert!(!Arc::ptr_eq(&app.targets[0], &kept[0]) && kept[0].is_stopped());
+        assert_eq!(app.targets[1].label.as_deref(), Some("db-2"));
+
+        quit.store(true, Ordering::Relaxed);
+        app.targets.iter().for_each(|t| t.stop());
+    }
+
+    #[tokio::test]
+    async fn test_add_target_cmds() {
+        let profile: &str = "seed = 1\n[[target]]\nname = \"web\"\ncount = 1";
+        let conf: Arc<MpConfig> = Arc::new(MpConfig {
+            sim: Some(Arc::new(Simulation::from_toml(profile).unwrap())),
+            ..MpConfig::default()
+        });
+        let quit: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+        let payload: Arc<[u8]> = Arc::from(vec![0u8; 32]);
+        let mut app: AppState = AppState::default();
+        let apply = async |app: &mut AppState, cmd: &str| {
+            app.pending.push(TargetCmd::Add(cmd.to_string()));
+            apply_target_cmds(app, &quit, &conf, &payload);
+            // the lookup runs in the background until a later round
+            while !app.lookups.is_empty() {
+                time::sleep(10 * MS).await;
+                apply_target_cmds(app, &quit, &conf, &payload);
+            }
+        };
+
+        apply(&mut app, "198.18.0.10").await;
+        assert_eq!(app.targets.len(), 1);
+        assert_eq!(app.status_message(), Some("Added 1 target(s)"));
+
+        // invalid input reopens the prompt with the error
+        apply(&mut app, "").await;
+        assert!(matches!(app.prompt, Some(Prompt::AddTarget(_, Some(_)))));
+        assert_eq!(app.targets.len(), 1);
+
+        quit.store(true, Ordering::Relaxed);
+        app.targets.iter().for_each(|t| t.stop());
+    }
+
+    #[test]
+    fn test_reply_order_wrap() {
+        let tgt: PingTarget = PingTarget::new([192, 0, 2, 1].into(), 3600, 100);
+        let mut stats = tgt.data.write();
+        for seq in [65534, 65535, 65536, 65538, 65537, 65539] {
+            stats.check_reply_order(seq);
+        }
+        assert_eq!(stats.reordered, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_reordering() {
+        // probe 2 takes longer than the interval, so probe 3 is answered first,
+        // and probe 1 is answered twice
+        let script: Vec<MockStep> = vec![
+            MockStep::Reply(10 * MS),
+            MockStep::Duplicate(10 * MS),
+            MockStep::Reply(1500 * MS),
+            MockStep::Reply(10 * MS),
+        ];
+        let tgt: Arc<PingTarget> = run_mock(script, 4).await;
+        let seqs: Vec<u64> = tgt.data.read().recent.iter().map(|r| r.seq).collect();
+        assert_eq!(seqs, [0, 1, 3, 2]);
+
+        let snap: StatsSnapshot = snapshot(&tgt);
+        assert_eq!((snap.sent, snap.recv), (4, 4));
+        assert_eq!((snap.dups, snap.reordered), (1, 1));
+        assert!(snap.hist.last_out_of_order);
+        assert_eq!(snap.max, Some(1_500_000));
+        assert_eq!(snap.percentiles, [(50.0, 10_000), (100.0, 1_500_000)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_seq_wrap() {
+        // start just below the 16-bit wrap, odd probes overtaken by the next even one
+        let tgt: Arc<PingTarget> = Arc::new(PingTarget::new([192, 0, 2, 1].into(), 3600, 100));
+        tgt.data.write().sent = 65534;
+        let script: Vec<MockStep> = vec![MockStep::Reply(10 * MS), MockStep::Reply(1500 * MS)];
+        let tgt: Arc<PingTarget> = run_mock_on(tgt, MpConfig::default(), script, 5).await;
+        let seqs: Vec<u64> = tgt.data.read().recent.iter().map(|r| r.seq).collect();
+        assert_eq!(seqs, [65534, 65536, 65535, 65538, 65537]);
+
+        let snap: StatsSnapshot = snapshot(&tgt);
+        assert_eq!((snap.hist.start_seq, snap.hist.end_seq), (65534, 65537));
+        assert_eq!(snap.latest_seq, 65538);
+        assert!(snap.hist.last_out_of_order);
+        assert_eq!(snap.reordered, 2);
+        assert_eq!(tgt.data.read().bursts.count(), 0);
+    }
+}
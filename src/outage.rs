@@ -0,0 +1,278 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Outage tracking: a target is considered down after N consecutive lost probes
+//! and up again on the first reply. Outages are logged with wall-clock times and
+//! summarized as availability, longest outage, MTBF and MTTR.
+
+use crate::utils::{format_duration, local_time_str};
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+/// Maximum number of outages kept in the log (the statistics cover all of them)
+const OUTAGE_LOG_MAX: usize = 100;
+
+/// A single outage of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Outage {
+    /// Send time of the first lost probe
+    pub start: SystemTime,
+    /// Send time of the first answered probe, `None` while still ongoing
+    pub end: Option<SystemTime>,
+    /// Number of lost probes during the outage
+    pub losses: u64,
+}
+
+impl Outage {
+    /// Length of the outage, up to `now` if it's still ongoing.
+    pub fn duration(&self, now: SystemTime) -> Duration {
+        let end: SystemTime = self.end.unwrap_or(now);
+        end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+impl Display for Outage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let start: String = local_time_str(self.start);
+        match self.end {
+            Some(end) => write!(
+                f,
+                "{start} - {} ({}, {} lost)",
+                local_time_str(end),
+                format_duration(self.duration(end)),
+                self.losses
+            ),
+            None => write!(f, "{start} - ongoing ({} lost)", self.losses),
+        }
+    }
+}
+
+/// Derived outage statistics of a target.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct OutageStats {
+    /// Number of outages, including an ongoing one
+    pub count: u64,
+    /// Whether the target is currently down
+    pub ongoing: bool,
+    /// Share of the observed time the target was up `[0.0 .. 1.0]`
+    pub availability: Option<f64>,
+    pub longest: Option<Duration>,
+    /// Mean time between failures: total uptime per outage
+    pub mtbf: Option<Duration>,
+    /// Mean time to repair: mean length of finished outages
+    pub mttr: Option<Duration>,
+}
+
+impl OutageStats {
+    /// Availability as formatted string.
+    pub fn availability_str(&self) -> String {
+        match self.availability {
+            Some(a) => format!("{:.2}%", 1e2 * a),
+            None => "-".to_string(),
+        }
+    }
+
+    /// One-line summary of the outage statistics.
+    pub fn summary(&self) -> String {
+        let dur = |d: Option<Duration>| d.map_or("-".to_string(), format_duration);
+        format!(
+            "outages: {}{}, availability: {}, longest: {}, MTBF: {}, MTTR: {}",
+            self.count,
+            if self.ongoing { " (down now)" } else { "" },
+            self.availability_str(),
+            dur(self.longest),
+            dur(self.mtbf),
+            dur(self.mttr),
+        )
+    }
+}
+
+/// Per-target outage tracker, fed with probe outcomes as they land.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct OutageTracker {
+    /// Send time of the first probe, ie. start of the observed period
+    since: Option<SystemTime>,
+    /// Consecutive losses so far, and the send time of the first one
+    run: u64,
+    run_start: Option<SystemTime>,
+    /// Send time of the latest answered probe
+    last_reply: Option<SystemTime>,
+    /// Recent outages, the last one may be ongoing
+    log: VecDeque<Outage>,
+    /// Totals over all outages (including ones dropped from the log)
+    count: u64,
+    repaired: u64,
+    downtime: Duration,
+    longest: Duration,
+}
+
+impl OutageTracker {
+    /// Record the outcome of a probe sent at `sent`. An outage is opened once
+    /// `threshold` consecutive probes are lost.
+    ///
+    /// Losses are reported only after the timeout, so they may land after the reply
+    /// to a later probe. Such stale losses don't count towards a new outage.
+    pub fn record(&mut self, sent: SystemTime, replied: bool, threshold: u32) {
+        if self.since.is_none_or(|s: SystemTime| sent < s) {
+            self.since = Some(sent);
+        }
+
+        if replied {
+            self.last_reply = Some(self.last_reply.map_or(sent, |t: SystemTime| t.max(sent)));
+            self.run = 0;
+            self.run_start = None;
+            if let Some(o) = self.current_mut() {
+                o.end = Some(sent);
+                let dur: Duration = o.duration(sent);
+                self.repaired += 1;
+                self.downtime += dur;
+                self.longest = self.longest.max(dur);
+            }
+            return;
+        }
+
+        if self.last_reply.is_some_and(|t: SystemTime| sent < t) {
+            // stale loss, still part of the previous outage if it falls within it
+            if let Some(o) = self.log.back_mut()
+                && o.end
+                    .is_some_and(|e: SystemTime| o.start <= sent && sent < e)
+            {
+                o.losses += 1;
+            }
+            return;
+        }
+        if let Some(o) = self.current_mut() {
+            o.losses += 1;
+            return;
+        }
+        self.run += 1;
+        if self.run_start.is_none_or(|s: SystemTime| sent < s) {
+            self.run_start = Some(sent);
+        }
+        if self.run >= threshold.max(1) as u64 {
+            if self.log.len() == OUTAGE_LOG_MAX {
+                self.log.pop_front();
+            }
+            self.log.push_back(Outage {
+                start: self.run_start.unwrap_or(sent),
+                end: None,
+                losses: self.run,
+            });
+            self.count += 1;
+            self.run = 0;
+            self.run_start = None;
+        }
+    }
+
+    /// The ongoing outage, if any.
+    fn current_mut(&mut self) -> Option<&mut Outage> {
+        self.log
+            .back_mut()
+            .filter(|o: &&mut Outage| o.end.is_none())
+    }
+
+    /// The ongoing outage, if any.
+    pub fn current(&self) -> Option<&Outage> {
+        self.log.back().filter(|o: &&Outage| o.end.is_none())
+    }
+
+    /// Logged outages, oldest first.
+    pub fn log(&self) -> &VecDeque<Outage> {
+        &self.log
+    }
+
+    /// Forget everything.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Derive [OutageStats] as of `now`.
+    pub fn stats(&self, now: SystemTime) -> OutageStats {
+        let ongoing: Option<Duration> = self.current().map(|o: &Outage| o.duration(now));
+        let downtime: Duration = self.downtime + ongoing.unwrap_or_default();
+        let observed: Option<Duration> = self
+            .since
+            .and_then(|s: SystemTime| now.duration_since(s).ok())
+            .filter(|d: &Duration| !d.is_zero());
+        let uptime: Option<Duration> = observed.map(|o: Duration| o.saturating_sub(downtime));
+
+        OutageStats {
+            count: self.count,
+            ongoing: ongoing.is_some(),
+            availability: match (observed, uptime) {
+                (Some(o), Some(u)) => Some(u.as_secs_f64() / o.as_secs_f64()),
+                _ => None,
+            },
+            longest: match self.count {
+                0 => None,
+                _ => Some(self.longest.max(ongoing.unwrap_or_default())),
+            },
+            mtbf: match self.count {
+                0 => None,
+                n => uptime.map(|u: Duration| u / n as u32),
+            },
+            mttr: match self.repaired {
+                0 => None,
+                n => Some(self.downtime / n as u32),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outage_tracker() {
+        let t0: SystemTime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+        let mut tr: OutageTracker = OutageTracker::default();
+
+        // 0-9 up, 10-19 down, 20-59 up, 60-61 lost (below threshold), 62-79 up,
+        // 80-89 down, 90-99 up
+        for sec in 0..100 {
+            let down: bool = matches!(sec, 10..20 | 60..62 | 80..90);
+            tr.record(at(sec), !down, 3);
+        }
+        assert_eq!(tr.log().len(), 2);
+        assert_eq!(tr.log()[0].start, at(10));
+        assert_eq!(tr.log()[0].end, Some(at(20)));
+        assert_eq!(tr.log()[0].losses, 10);
+        assert!(tr.current().is_none());
+
+        let st: OutageStats = tr.stats(at(100));
+        assert_eq!(st.count, 2);
+        assert!(!st.ongoing);
+        assert_eq!(st.availability, Some(0.8));
+        assert_eq!(st.availability_str(), "80.00%");
+        assert_eq!(st.longest, Some(Duration::from_secs(10)));
+        assert_eq!(st.mtbf, Some(Duration::from_secs(40)));
+        assert_eq!(st.mttr, Some(Duration::from_secs(10)));
+
+        // a late loss from within the last outage, and a stale one from after it
+        tr.record(at(89), false, 3);
+        assert_eq!(tr.log()[1].losses, 11);
+        tr.record(at(98), false, 3);
+
+        // a new outage, still ongoing
+        for sec in 100..105 {
+            tr.record(at(sec), false, 3);
+        }
+        let st: OutageStats = tr.stats(at(120));
+        assert!(st.ongoing);
+        assert_eq!(
+            tr.current().map(|o| (o.start, o.losses)),
+            Some((at(100), 5))
+        );
+        assert_eq!(st.longest, Some(Duration::from_secs(20)));
+        assert_eq!(st.mttr, Some(Duration::from_secs(10)));
+
+        tr.clear();
+        assert_eq!(tr.stats(at(120)), OutageStats::default());
+    }
+}
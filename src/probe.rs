@@ -0,0 +1,522 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Probe transports behind the [Prober] trait: ICMP echo (surge-ping), TCP
+//! handshake, UDP datagram and a scripted mock for testing.
+
+use crate::{
+    errors::ErrorCategory,
+    icmperr::{IcmpErrorListener, IcmpReport, ProbeKey},
+    ident::IdLease,
+};
+use futures::future::{BoxFuture, FutureExt};
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use surge_ping::{Client, IcmpPacket, PingIdentifier, PingSequence, Pinger, SurgeError};
+use tokio::{
+    net::{TcpStream, UdpSocket},
+    time,
+};
+
+/// Protocol used to probe a target.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Proto {
+    /// ICMP echo request/reply
+    #[default]
+    Icmp,
+    /// TCP handshake to the given port
+    Tcp(u16),
+    /// UDP datagram to the given port, answered by an echo or port unreachable
+    Udp(u16),
+}
+
+impl Display for Proto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Proto::Icmp => write!(f, "icmp"),
+            Proto::Tcp(port) => write!(f, "tcp/{port}"),
+            Proto::Udp(port) => write!(f, "udp/{port}"),
+        }
+    }
+}
+
+/// Successful probe result, ie. the target host answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProbeReply {
+    pub rtt: Duration,
+    /// The host answered but the port is closed (TCP RST or ICMP port unreachable)
+    pub closed: bool,
+    /// TTL (IPv4) or hop limit (IPv6) of the reply, if known
+    pub ttl: Option<u8>,
+}
+
+impl ProbeReply {
+    /// Create a [ProbeReply] for a regular reply.
+    pub fn new(rtt: Duration) -> Self {
+        Self {
+            rtt,
+            closed: false,
+            ttl: None,
+        }
+    }
+
+    /// Create a [ProbeReply] for a "port closed" type reply.
+    pub fn closed(rtt: Duration) -> Self {
+        Self {
+            rtt,
+            closed: true,
+            ttl: None,
+        }
+    }
+
+    /// Set the reply TTL. Intended for chaining with `new()`.
+    pub fn with_ttl(mut self, ttl: Option<u8>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+/// Failed probe, ie. no usable answer from the target host.
+#[derive(Debug)]
+pub(crate) enum ProbeError {
+    Timeout,
+    Icmp(SurgeError),
+    Io(io::Error),
+    /// Destination unreachable reported (by ICMP) in response to the probe
+    Unreachable(io::Error),
+    /// ICMP error reply to an echo request, see [IcmpErrorListener]
+    IcmpError(IcmpReport),
+}
+
+impl ProbeError {
+    /// Cause of the failure for the per-category counters.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            ProbeError::Timeout => ErrorCategory::Timeout,
+            ProbeError::Unreachable(_) => ErrorCategory::Unreachable,
+            ProbeError::IcmpError(r) => r.category(),
+            ProbeError::Io(e) | ProbeError::Icmp(SurgeError::IOError(e)) => io_category(e),
+            ProbeError::Icmp(SurgeError::NetworkError) => ErrorCategory::SendFailed,
+            ProbeError::Icmp(
+                SurgeError::MalformedPacket(_)
+                | SurgeError::IncorrectBufferSize
+                | SurgeError::EchoRequestPacket
+                | SurgeError::IdenticalRequests { .. },
+            ) => ErrorCategory::BadReply,
+            ProbeError::Icmp(_) => ErrorCategory::Other,
+        }
+    }
+}
+
+/// Category of an I/O error when sending a probe. Routing failures and full
+/// socket buffers mean the probe never left the host.
+fn io_category(e: &io::Error) -> ErrorCategory {
+    match (e.kind(), e.raw_os_error()) {
+        (_, Some(libc::ENOBUFS)) => ErrorCategory::SendFailed,
+        (
+            ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+            | ErrorKind::NetworkDown
+            | ErrorKind::AddrNotAvailable
+            | ErrorKind::PermissionDenied,
+            _,
+        ) => ErrorCategory::SendFailed,
+        (ErrorKind::TimedOut, _) => ErrorCategory::Timeout,
+        _ => ErrorCategory::Other,
+    }
+}
+
+impl Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::Timeout => write!(f, "timeout"),
+            ProbeError::Icmp(e) => write!(f, "{e}"),
+            ProbeError::Io(e) | ProbeError::Unreachable(e) => write!(f, "{e}"),
+            ProbeError::IcmpError(r) => write!(f, "{r}"),
+        }
+    }
+}
+
+impl From<SurgeError> for ProbeError {
+    fn from(e: SurgeError) -> Self {
+        match e {
+            SurgeError::Timeout { .. } => ProbeError::Timeout,
+            e => ProbeError::Icmp(e),
+        }
+    }
+}
+
+pub(crate) type ProbeResult = Result<ProbeReply, ProbeError>;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Transport for sending probes to a target and waiting for the outcome.
+///
+/// The returned future is `'static` so it can be spawned as its own task,
+/// allowing several probes to the same target to be in flight at once.
+pub(crate) trait Prober: Send + Sync {
+    /// Send probe number `seq` to `addr` and wait at most `timeout` for an answer.
+    fn probe(
+        &self,
+        addr: IpAddr,
+        seq: u16,
+        payload: Arc<[u8]>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, ProbeResult>;
+
+    /// Whether this prober sees duplicate replies, see [Prober::duplicates].
+    fn sees_duplicates(&self) -> bool {
+        false
+    }
+
+    /// Number of duplicate replies to probe `seq` to `addr`. Asked once, after the
+    /// probe has been answered and its timeout has passed.
+    fn duplicates(&self, _addr: IpAddr, _seq: u16) -> u32 {
+        0
+    }
+}
+
+/// ICMP echo probes using a shared [surge_ping::Client].
+pub(crate) struct IcmpProber {
+    client: Arc<Client>,
+    id: PingIdentifier,
+    /// Keeps `id` reserved for this prober
+    _lease: IdLease,
+    /// Source of ICMP error replies, if raw sockets are available
+    errors: Option<Arc<IcmpErrorListener>>,
+}
+
+impl IcmpProber {
+    /// Create a new [IcmpProber] with the ICMP identifier leased on `client`. A timed
+    /// out probe is reported as an ICMP error instead if `errors` has received one for it.
+    pub fn new(
+        client: Arc<Client>,
+        lease: IdLease,
+        errors: Option<Arc<IcmpErrorListener>>,
+    ) -> Self {
+        Self {
+            client,
+            id: PingIdentifier(lease.id()),
+            _lease: lease,
+            errors,
+        }
+    }
+}
+
+impl Prober for IcmpProber {
+    fn probe(
+        &self,
+        addr: IpAddr,
+        seq: u16,
+        payload: Arc<[u8]>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, ProbeResult> {
+        let (client, id, errors) = (self.client.clone(), self.id, self.errors.clone());
+        async move {
+            // The pinger is created anew for each probe (ie. async context)
+            let mut pinger: Pinger = client.pinger(addr, id).await;
+            pinger.timeout(timeout);
+            match pinger.ping(PingSequence(seq), &payload).await {
+                Ok((packet, rtt)) => Ok(ProbeReply::new(rtt).with_ttl(reply_ttl(&packet))),
+                Err(SurgeError::Timeout { .. }) => {
+                    let key: ProbeKey = ProbeKey {
+                        dest: addr,
+                        ident: id.0,
+                        seq,
+                    };
+                    match errors.and_then(|l: Arc<IcmpErrorListener>| l.take(&key)) {
+                        Some(report) => Err(ProbeError::IcmpError(report)),
+                        None => Err(ProbeError::Timeout),
+                    }
+                }
+                Err(e) => Err(e.into()),
+            }
+        }
+        .boxed()
+    }
+
+    /// Echo replies are counted by the listener of ICMP errors, so duplicates
+    /// are only seen with raw sockets.
+    fn sees_duplicates(&self) -> bool {
+        self.errors.is_some()
+    }
+
+    fn duplicates(&self, addr: IpAddr, seq: u16) -> u32 {
+        let key: ProbeKey = ProbeKey {
+            dest: addr,
+            ident: self.id.0,
+            seq,
+        };
+        match &self.errors {
+            Some(l) => l.take_echoes(&key).saturating_sub(1),
+            None => 0,
+        }
+    }
+}
+
+/// TTL (IPv4) or hop limit (IPv6) of an echo reply. Not available with unprivileged
+/// datagram sockets, where it's reported as missing (IPv4) or zero (IPv6).
+fn reply_ttl(packet: &IcmpPacket) -> Option<u8> {
+    match packet {
+        IcmpPacket::V4(p) => p.get_ttl(),
+        IcmpPacket::V6(p) => Some(p.get_max_hop_limit()).filter(|h: &u8| *h > 0),
+    }
+}
+
+/// TCP handshake probes to a port, see [tcp_probe].
+pub(crate) struct TcpProber(pub u16);
+
+impl Prober for TcpProber {
+    fn probe(
+        &self,
+        addr: IpAddr,
+        _seq: u16,
+        _payload: Arc<[u8]>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, ProbeResult> {
+        tcp_probe(SocketAddr::new(addr, self.0), timeout).boxed()
+    }
+}
+
+/// UDP datagram probes to a port, see [udp_probe].
+pub(crate) struct UdpProber(pub u16);
+
+impl Prober for UdpProber {
+    fn probe(
+        &self,
+        addr: IpAddr,
+        _seq: u16,
+        payload: Arc<[u8]>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, ProbeResult> {
+        let addr: SocketAddr = SocketAddr::new(addr, self.0);
+        async move { udp_probe(addr, &payload, timeout).await }.boxed()
+    }
+}
+
+/* ---------------------------------------- */
+
+/// Scripted outcome of a single [MockProber] probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MockStep {
+    /// Reply after the given delay
+    Reply(Duration),
+    /// Reply after the given delay, plus a duplicate of it
+    Duplicate(Duration),
+    /// Reply after the given delay with the given TTL
+    ReplyTtl(Duration, u8),
+    /// "Port closed" reply after the given delay
+    Closed(Duration),
+    /// No reply, ie. the probe times out
+    Loss,
+    /// Immediate failure with the given error
+    Error(ErrorKind),
+    /// ICMP error reply arriving after the given delay. Like [IcmpProber], the error
+    /// is reported only once the probe times out.
+    IcmpError(IcmpReport, Duration),
+}
+
+/// Deterministic [Prober] which plays back a script instead of touching the network.
+///
+/// Probe `seq` gets step `seq % script.len()`, so the outcome of each probe is known
+/// in advance. Replies arrive after their scripted delay, which means a long delay
+/// followed by a short one reorders the replies. Delays beyond the timeout are losses.
+/// Combined with paused tokio time, tests run instantly and are fully repeatable.
+pub(crate) struct MockProber {
+    script: Vec<MockStep>,
+}
+
+impl MockProber {
+    /// Create a new [MockProber]. An empty script means every probe is lost.
+    pub fn new(script: Vec<MockStep>) -> Self {
+        Self { script }
+    }
+
+    /// Scripted step for probe `seq`.
+    pub fn step(&self, seq: u16) -> MockStep {
+        match self.script.len() {
+            0 => MockStep::Loss,
+            n => self.script[seq as usize % n],
+        }
+    }
+}
+
+impl Prober for MockProber {
+    fn probe(
+        &self,
+        _addr: IpAddr,
+        seq: u16,
+        _payload: Arc<[u8]>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, ProbeResult> {
+        play_step(self.step(seq), timeout)
+    }
+
+    fn sees_duplicates(&self) -> bool {
+        true
+    }
+
+    fn duplicates(&self, _addr: IpAddr, seq: u16) -> u32 {
+        matches!(self.step(seq), MockStep::Duplicate(_)) as u32
+    }
+}
+
+/// Play back a single scripted [MockStep] as a probe outcome.
+pub(crate) fn play_step(step: MockStep, timeout: Duration) -> BoxFuture<'static, ProbeResult> {
+    async move {
+        match step {
+            MockStep::Reply(d) | MockStep::Duplicate(d) if d <= timeout => {
+                time::sleep(d).await;
+                Ok(ProbeReply::new(d))
+            }
+            MockStep::ReplyTtl(d, ttl) if d <= timeout => {
+                time::sleep(d).await;
+                Ok(ProbeReply::new(d).with_ttl(Some(ttl)))
+            }
+            MockStep::Closed(d) if d <= timeout => {
+                time::sleep(d).await;
+                Ok(ProbeReply::closed(d))
+            }
+            MockStep::Error(kind) => Err(ProbeError::Io(kind.into())),
+            MockStep::IcmpError(report, d) if d <= timeout => {
+                time::sleep(timeout).await;
+                Err(ProbeError::IcmpError(report))
+            }
+            _ => {
+                time::sleep(timeout).await;
+                Err(ProbeError::Timeout)
+            }
+        }
+    }
+    .boxed()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Measure the TCP handshake time to `addr`.
+///
+/// A completed handshake is a regular reply. A refused connection (RST) still
+/// proves the host is up and its RTT is as good as a SYN/ACK, so it counts as a
+/// "closed" reply. The connection is closed right away in either case.
+pub(crate) async fn tcp_probe(addr: SocketAddr, timeout: Duration) -> ProbeResult {
+    let start: time::Instant = time::Instant::now();
+    match time::timeout(timeout, TcpStream::connect(addr)).await {
+        Ok(Ok(_stream)) => Ok(ProbeReply::new(start.elapsed())),
+        Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => {
+            Ok(ProbeReply::closed(start.elapsed()))
+        }
+        Ok(Err(e)) => Err(ProbeError::Io(e)),
+        Err(_) => Err(ProbeError::Timeout),
+    }
+}
+
+/// Send `payload` in a UDP datagram to `addr` and wait for an answer.
+///
+/// Any datagram back from `addr` (eg. from an echo service) is a regular reply.
+/// An ICMP port unreachable, reported by the kernel as a refused connection on
+/// the connected socket, means the host is up but nothing listens on the port.
+/// Host and network unreachable after a successful send came from a router.
+pub(crate) async fn udp_probe(addr: SocketAddr, payload: &[u8], timeout: Duration) -> ProbeResult {
+    let bind: SocketAddr = match addr.ip() {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let sock: UdpSocket = UdpSocket::bind(bind).await.map_err(ProbeError::Io)?;
+    sock.connect(addr).await.map_err(ProbeError::Io)?;
+
+    let start: time::Instant = time::Instant::now();
+    let exchange = async {
+        sock.send(payload).await.map_err(ProbeError::Io)?;
+        // contents of the answer don't matter, only that there is one
+        let mut buf: [u8; 64] = [0; 64];
+        sock.recv(&mut buf)
+            .await
+            .map_err(|e: io::Error| match e.kind() {
+                ErrorKind::HostUnreachable | ErrorKind::NetworkUnreachable => {
+                    ProbeError::Unreachable(e)
+                }
+                _ => ProbeError::Io(e),
+            })
+    };
+    match time::timeout(timeout, exchange).await {
+        Ok(Ok(_)) => Ok(ProbeReply::new(start.elapsed())),
+        Ok(Err(ProbeError::Io(e))) if e.kind() == ErrorKind::ConnectionRefused => {
+            Ok(ProbeReply::closed(start.elapsed()))
+        }
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(ProbeError::Timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_tcp_probe_loopback() {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let timeout: Duration = Duration::from_secs(1);
+
+        let reply: ProbeReply = tcp_probe(addr, timeout).await.unwrap();
+        assert!(!reply.closed);
+        assert!(reply.rtt < timeout);
+
+        // nothing listening anymore -> RST from the loopback stack
+        drop(listener);
+        let reply: ProbeReply = tcp_probe(addr, timeout).await.unwrap();
+        assert!(reply.closed);
+    }
+
+    #[tokio::test]
+    async fn test_udp_probe_loopback() {
+        let server: UdpSocket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = server.local_addr().unwrap();
+        let timeout: Duration = Duration::from_secs(1);
+        let echo = tokio::spawn(async move {
+            let mut buf: [u8; 64] = [0; 64];
+            let (n, peer) = server.recv_from(&mut buf).await.unwrap();
+            server.send_to(&buf[..n], peer).await.unwrap();
+        });
+
+        let reply: ProbeReply = udp_probe(addr, b"ping", timeout).await.unwrap();
+        assert!(!reply.closed);
+        echo.await.unwrap(); // server socket is dropped here
+
+        // nothing listening anymore -> ICMP port unreachable
+        let reply: ProbeReply = udp_probe(addr, b"ping", timeout).await.unwrap();
+        assert!(reply.closed);
+    }
+
+    #[test]
+    fn test_error_category() {
+        let io = |kind: ErrorKind| ProbeError::Io(kind.into());
+        assert_eq!(ProbeError::Timeout.category(), ErrorCategory::Timeout);
+        assert_eq!(
+            io(ErrorKind::NetworkUnreachable).category(),
+            ErrorCategory::SendFailed
+        );
+        let nobufs: io::Error = io::Error::from_raw_os_error(libc::ENOBUFS);
+        assert_eq!(
+            ProbeError::Icmp(SurgeError::IOError(nobufs)).category(),
+            ErrorCategory::SendFailed
+        );
+        let unreach: io::Error = ErrorKind::HostUnreachable.into();
+        assert_eq!(
+            ProbeError::Unreachable(unreach).category(),
+            ErrorCategory::Unreachable
+        );
+        assert_eq!(
+            ProbeError::Icmp(SurgeError::EchoRequestPacket).category(),
+            ErrorCategory::BadReply
+        );
+        assert_eq!(io(ErrorKind::InvalidData).category(), ErrorCategory::Other);
+    }
+}
@@ -0,0 +1,313 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Status classification rules (lossy, flappy, laggy, unreachable) and the minimum
+//! dwell time of a status. Configurable globally from the command line or the
+//! `[status]` table of a config file, and per target in the targets file.
+
+use crate::{structs::PingStatus, utils::parse_float_into_duration_or_zero};
+use serde::Deserialize;
+use std::{
+    fs,
+    mem::{Discriminant, discriminant},
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Effective status classification rules of a target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct StatusRules {
+    /// Lossy if at least `.1` (ratio) of the last `.0` probes are lost
+    pub lossy: (usize, f64),
+    /// Flappy if the last `.0` probes flip between lost/answered at least `.1` times
+    pub flappy: (usize, usize),
+    /// Laggy if the mean RTT of the last `.0` replies is over `.1` times the long-term mean
+    pub laggy: (usize, f64),
+    /// Not reachable if nothing at all was answered after this many probes
+    pub unreachable: u64,
+    /// Minimum time an OK/lossy/flappy/laggy status is kept before switching between them
+    pub dwell: Duration,
+}
+
+impl Default for StatusRules {
+    fn default() -> Self {
+        Self {
+            lossy: (5, 0.5),
+            flappy: (10, 5),
+            laggy: (10, 2.0),
+            unreachable: 10,
+            dwell: Duration::ZERO,
+        }
+    }
+}
+
+/// Overrides of [StatusRules]. `None` means "use the value from the level above"
+/// (target -> command line -> config file -> defaults).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct RuleOverrides {
+    pub lossy: Option<(usize, f64)>,
+    pub flappy: Option<(usize, usize)>,
+    pub laggy: Option<(usize, f64)>,
+    pub unreachable: Option<u64>,
+    pub dwell: Option<Duration>,
+}
+
+impl RuleOverrides {
+    /// Whether nothing is overridden.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill in the values not set here from `lower`.
+    pub fn or(&self, lower: &RuleOverrides) -> RuleOverrides {
+        RuleOverrides {
+            lossy: self.lossy.or(lower.lossy),
+            flappy: self.flappy.or(lower.flappy),
+            laggy: self.laggy.or(lower.laggy),
+            unreachable: self.unreachable.or(lower.unreachable),
+            dwell: self.dwell.or(lower.dwell),
+        }
+    }
+
+    /// Apply these overrides on top of `base`.
+    pub fn apply(&self, base: &StatusRules) -> StatusRules {
+        StatusRules {
+            lossy: self.lossy.unwrap_or(base.lossy),
+            flappy: self.flappy.unwrap_or(base.flappy),
+            laggy: self.laggy.unwrap_or(base.laggy),
+            unreachable: self.unreachable.unwrap_or(base.unreachable),
+            dwell: self.dwell.unwrap_or(base.dwell),
+        }
+    }
+
+    /// Set a rule by name (as in the targets file), eg. `set("laggy", "20:1.5")`.
+    /// Returns `Ok(false)` if `key` is not a rule name.
+    pub fn set(&mut self, key: &str, val: &str) -> Result<bool, String> {
+        match key {
+            "lossy" => self.lossy = Some(parse_lossy(val)?),
+            "flappy" => self.flappy = Some(parse_flappy(val)?),
+            "laggy" => self.laggy = Some(parse_laggy(val)?),
+            "unreachable" => self.unreachable = Some(parse_unreachable(val)?),
+            "dwell" => self.dwell = Some(parse_dwell(val)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// `[status]` table of the config file, with values in the command line syntax.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct StatusConfig {
+    lossy: Option<String>,
+    flappy: Option<String>,
+    laggy: Option<String>,
+    unreachable: Option<u64>,
+    dwell: Option<f64>,
+}
+
+impl TryFrom<StatusConfig> for RuleOverrides {
+    type Error = String;
+
+    fn try_from(c: StatusConfig) -> Result<Self, Self::Error> {
+        let mut rules: RuleOverrides = RuleOverrides::default();
+        for (key, val) in [("lossy", c.lossy), ("flappy", c.flappy), ("laggy", c.laggy)] {
+            if let Some(v) = val {
+                rules.set(key, &v).map_err(|e| format!("{key}: {e}"))?;
+            }
+        }
+        if let Some(n) = c.unreachable {
+            rules.set("unreachable", &n.to_string())?;
+        }
+        if let Some(secs) = c.dwell {
+            rules.set("dwell", &secs.to_string())?;
+        }
+        Ok(rules)
+    }
+}
+
+/// Config file (`--config`), currently only holding the `[status]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    status: StatusConfig,
+}
+
+/// Load the status rules from a config file.
+pub(crate) fn load_config(path: &Path) -> Result<RuleOverrides, String> {
+    let content: String =
+        fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    let file: ConfigFile =
+        toml::from_str(&content).map_err(|e| format!("{}: {e}", path.display()))?;
+    RuleOverrides::try_from(file.status).map_err(|e| format!("{}: [status] {e}", path.display()))
+}
+
+/* ---------------------------------------- */
+
+/// Parse `N:VALUE` into its two parts.
+fn parse_pair<A: FromStr, B: FromStr>(s: &str) -> Result<(A, B), String> {
+    let err = || format!("Expected N:VALUE, got '{s}'");
+    let (a, b) = s.split_once(':').ok_or_else(err)?;
+    match (a.trim().parse::<A>(), b.trim().parse::<B>()) {
+        (Ok(a), Ok(b)) => Ok((a, b)),
+        _ => Err(err()),
+    }
+}
+
+/// Parse a lossy rule `N:RATIO`, eg. `5:0.5`.
+pub(crate) fn parse_lossy(s: &str) -> Result<(usize, f64), String> {
+    match parse_pair::<usize, f64>(s)? {
+        (n, r) if (1..=1000).contains(&n) && r > 0.0 && r <= 1.0 => Ok((n, r)),
+        _ => Err(format!(
+            "Invalid lossy rule '{s}' (N:RATIO, N 1-1000, RATIO 0-1)"
+        )),
+    }
+}
+
+/// Parse a flappy rule `N:TRANSITIONS`, eg. `10:5`.
+pub(crate) fn parse_flappy(s: &str) -> Result<(usize, usize), String> {
+    match parse_pair::<usize, usize>(s)? {
+        (n, t) if (2..=1000).contains(&n) && t >= 1 && t < n => Ok((n, t)),
+        _ => Err(format!(
+            "Invalid flappy rule '{s}' (N:TRANSITIONS, N 2-1000, TRANSITIONS 1-N)"
+        )),
+    }
+}
+
+/// Parse a laggy rule `N:FACTOR`, eg. `10:2.0`.
+pub(crate) fn parse_laggy(s: &str) -> Result<(usize, f64), String> {
+    match parse_pair::<usize, f64>(s)? {
+        (n, f) if (1..=1000).contains(&n) && f.is_finite() && f > 1.0 => Ok((n, f)),
+        _ => Err(format!(
+            "Invalid laggy rule '{s}' (N:FACTOR, N 1-1000, FACTOR > 1)"
+        )),
+    }
+}
+
+/// Parse the number of unanswered probes before a target is "not reachable".
+pub(crate) fn parse_unreachable(s: &str) -> Result<u64, String> {
+    match s.parse::<u64>() {
+        Ok(n) if n >= 1 => Ok(n),
+        _ => Err(format!("Invalid probe count '{s}'")),
+    }
+}
+
+/// Parse a dwell time in seconds, `0` disables it.
+pub(crate) fn parse_dwell(s: &str) -> Result<Duration, String> {
+    parse_float_into_duration_or_zero(s)
+}
+
+/* ---------------------------------------- */
+
+/// Whether the status is one of those subject to the dwell time.
+#[inline]
+fn dwells(status: &PingStatus) -> bool {
+    matches!(
+        status,
+        PingStatus::Ok | PingStatus::Lossy | PingStatus::Flappy | PingStatus::Laggy
+    )
+}
+
+/// A status waiting to be applied, and since when it has been seen without a break.
+pub(crate) type PendingStatus = Option<(Discriminant<PingStatus>, Instant)>;
+
+/// Apply the minimum dwell time (hysteresis): a switch between OK, lossy, flappy and
+/// laggy only happens once the new status has been seen on every update for `dwell`.
+/// Until then `pending` holds it and the current status is kept (`None`). Other
+/// changes (eg. to timeout or paused) always go through.
+pub(crate) fn dwell_filter(
+    current: &PingStatus,
+    pending: &mut PendingStatus,
+    next: PingStatus,
+    dwell: Duration,
+    now: Instant,
+) -> Option<PingStatus> {
+    let switch: bool = discriminant(current) != discriminant(&next);
+    if dwell.is_zero() || !switch || !dwells(current) || !dwells(&next) {
+        *pending = None;
+        return Some(next);
+    }
+    match *pending {
+        Some((kind, since)) if kind == discriminant(&next) => {
+            match now.duration_since(since) >= dwell {
+                true => {
+                    *pending = None;
+                    Some(next)
+                }
+                false => None,
+            }
+        }
+        _ => {
+            *pending = Some((discriminant(&next), now));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        assert_eq!(parse_lossy("20:0.1"), Ok((20, 0.1)));
+        assert!(parse_lossy("20:1.5").is_err());
+        assert!(parse_flappy("10:10").is_err());
+        assert!(parse_laggy("10:0.5").is_err());
+        assert!(parse_laggy("10").is_err());
+        assert_eq!(parse_dwell("0"), Ok(Duration::ZERO));
+
+        let mut tgt: RuleOverrides = RuleOverrides::default();
+        assert_eq!(tgt.set("laggy", "30:1.5"), Ok(true));
+        assert_eq!(tgt.set("label", "x"), Ok(false));
+        let global: RuleOverrides = RuleOverrides {
+            laggy: Some((5, 3.0)),
+            unreachable: Some(50),
+            ..Default::default()
+        };
+        let rules: StatusRules = tgt.or(&global).apply(&StatusRules::default());
+        assert_eq!(rules.laggy, (30, 1.5));
+        assert_eq!(rules.unreachable, 50);
+        assert_eq!(rules.lossy, StatusRules::default().lossy);
+
+        let conf: StatusConfig = toml::from_str("lossy = \"10:0.3\"\ndwell = 2.5").unwrap();
+        let file: RuleOverrides = conf.try_into().unwrap();
+        assert_eq!(file.lossy, Some((10, 0.3)));
+        assert_eq!(file.dwell, Some(Duration::from_millis(2500)));
+        assert!(toml::from_str::<StatusConfig>("lagy = \"1:2\"").is_err());
+    }
+
+    #[test]
+    fn test_dwell_filter() {
+        let t0: Instant = Instant::now();
+        let dwell: Duration = Duration::from_secs(5);
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+        let mut pending: PendingStatus = None;
+        let mut f = |cur: &PingStatus, next: PingStatus, now: u64| {
+            dwell_filter(cur, &mut pending, next, dwell, at(now))
+        };
+        let ok: PingStatus = PingStatus::Ok;
+
+        // laggy for a moment only after a long OK stretch -> stays OK
+        assert!(f(&ok, PingStatus::Laggy, 100).is_none());
+        assert!(matches!(f(&ok, PingStatus::Ok, 101), Some(PingStatus::Ok)));
+        assert!(f(&ok, PingStatus::Laggy, 102).is_none());
+        assert!(f(&ok, PingStatus::Laggy, 106).is_none());
+        // laggy on every update for the dwell time -> switch
+        assert!(matches!(
+            f(&ok, PingStatus::Laggy, 107),
+            Some(PingStatus::Laggy)
+        ));
+        // timeouts always go through
+        assert!(matches!(
+            f(&PingStatus::Laggy, PingStatus::Timeout, 108),
+            Some(PingStatus::Timeout)
+        ));
+        // no dwell configured
+        let mut none: PendingStatus = None;
+        let next = dwell_filter(&ok, &mut none, PingStatus::Laggy, Duration::ZERO, t0);
+        assert!(matches!(next, Some(PingStatus::Laggy)));
+    }
+}
@@ -0,0 +1,335 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Simulation mode: synthetic targets with configurable latency, loss, outages
+//! and flapping, probed through [SimProber] instead of the network.
+//!
+//! Profile format (TOML):
+//!
+//! ```toml
+//! seed = 42                   # optional, for repeatable runs
+//!
+//! [[target]]
+//! name = "web"                # label, numbered if count > 1
+//! group = "dc1"
+//! count = 20                  # number of identical targets [default: 1]
+//! latency = 15.0              # mean RTT in ms [default: 20]
+//! jitter = 3.0                # RTT spread in ms [default: 2]
+//! distribution = "normal"     # normal | uniform | exponential
+//! loss = 0.01                 # random loss probability [0-1]
+//! outages = [[60, 90]]        # down between these seconds since start
+//! flap = [10, 5]              # alternate: up 10s, down 5s
+//! closed = false              # answer with "port closed" replies
+//! ```
+
+use crate::{
+    probe::{MockStep, ProbeResult, Prober, play_step},
+    targets::TargetSpec,
+};
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use rand::{Rng, SeedableRng, random, rngs::StdRng};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    fs,
+    net::{IpAddr, Ipv4Addr},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Simulated targets get addresses from the benchmarking range 198.18.0.0/15
+const SIM_BASE: Ipv4Addr = Ipv4Addr::new(198, 18, 0, 1);
+const SIM_MAX_TARGETS: u32 = 131_070;
+/// Lower bound for a simulated RTT (ms)
+const SIM_MIN_RTT: f64 = 0.01;
+
+/// Shape of the simulated RTT distribution around the mean `latency`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Distribution {
+    /// Gaussian with standard deviation `jitter`
+    #[default]
+    Normal,
+    /// Evenly spread within `latency ± jitter`
+    Uniform,
+    /// `latency` plus an exponential tail with mean `jitter` (occasional spikes)
+    Exponential,
+}
+
+/// Behaviour of one or more simulated targets, see the module docs.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SimProfile {
+    pub name: Option<String>,
+    pub group: Option<String>,
+    pub count: u32,
+    pub latency: f64,
+    pub jitter: f64,
+    pub distribution: Distribution,
+    pub loss: f64,
+    pub outages: Vec<[f64; 2]>,
+    pub flap: Option<[f64; 2]>,
+    pub closed: bool,
+}
+
+impl Default for SimProfile {
+    fn default() -> Self {
+        Self {
+            name: None,
+            group: None,
+            count: 1,
+            latency: 20.0,
+            jitter: 2.0,
+            distribution: Distribution::Normal,
+            loss: 0.0,
+            outages: vec![],
+            flap: None,
+            closed: false,
+        }
+    }
+}
+
+impl SimProfile {
+    /// Check the values for sanity.
+    fn validate(&self) -> Result<(), String> {
+        if self.count == 0 {
+            return Err("count must be at least 1".to_string());
+        }
+        if !(self.latency >= 0.0 && self.jitter >= 0.0) {
+            return Err("latency and jitter must be non-negative".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.loss) {
+            return Err(format!("loss must be within [0-1], got {}", self.loss));
+        }
+        if let Some([s, e]) = self.outages.iter().find(|[s, e]| !(*s >= 0.0 && s < e)) {
+            return Err(format!("invalid outage window [{s}, {e}]"));
+        }
+        if let Some([up, down]) = self.flap
+            && !(up > 0.0 && down > 0.0)
+        {
+            return Err("flap periods must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the target is down (scheduled outage or flap) at `secs` since start.
+    pub fn is_down(&self, secs: f64) -> bool {
+        if self.outages.iter().any(|[s, e]| (*s..*e).contains(&secs)) {
+            return true;
+        }
+        match self.flap {
+            Some([up, down]) => secs % (up + down) >= up,
+            None => false,
+        }
+    }
+
+    /// Draw a random RTT (ms) from the configured distribution.
+    pub fn sample_rtt<R: Rng>(&self, rng: &mut R) -> f64 {
+        // (0, 1] so that ln() is always finite
+        let mut unit = || 1.0 - rng.random::<f64>();
+        let rtt: f64 = match self.distribution {
+            Distribution::Normal => {
+                // Box-Muller transform
+                let (u1, u2) = (unit(), unit());
+                self.latency + self.jitter * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+            }
+            Distribution::Uniform => self.latency + self.jitter * (2.0 * unit() - 1.0),
+            Distribution::Exponential => self.latency - self.jitter * unit().ln(),
+        };
+        rtt.max(SIM_MIN_RTT)
+    }
+}
+
+/// Top level of a simulation profile file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SimFile {
+    seed: Option<u64>,
+    target: Vec<SimProfile>,
+}
+
+/* ---------------------------------------- */
+
+/// A loaded simulation: the synthetic targets and their behaviour.
+#[derive(Debug)]
+pub(crate) struct Simulation {
+    /// Target specifications for all simulated targets
+    pub specs: Vec<TargetSpec>,
+    profiles: HashMap<IpAddr, Arc<SimProfile>>,
+    seed: u64,
+    start: Instant,
+}
+
+impl Simulation {
+    /// Load a simulation profile from a TOML file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content: String =
+            fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+        Self::from_toml(&content).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Parse a simulation profile, see the module docs for the format.
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let file: SimFile = toml::from_str(content).map_err(|e| e.to_string())?;
+        if file.target.is_empty() {
+            return Err("No simulated targets defined".to_string());
+        }
+
+        let mut specs: Vec<TargetSpec> = Vec::new();
+        let mut profiles: HashMap<IpAddr, Arc<SimProfile>> = HashMap::new();
+        for (num, profile) in file.target.into_iter().enumerate() {
+            profile
+                .validate()
+                .map_err(|e| format!("target #{}: {e}", num + 1))?;
+            let profile: Arc<SimProfile> = profile.into();
+            for i in 1..=profile.count {
+                let index: u32 = specs.len() as u32;
+                if index >= SIM_MAX_TARGETS {
+                    return Err(format!("Too many targets (max. {SIM_MAX_TARGETS})"));
+                }
+                let addr: IpAddr = Ipv4Addr::from(u32::from(SIM_BASE) + index).into();
+                let mut spec: TargetSpec = TargetSpec::new(addr);
+                spec.label = match (&profile.name, profile.count) {
+                    (Some(name), 1) => Some(name.clone()),
+                    (Some(name), _) => Some(format!("{name}-{i}")),
+                    (None, _) => None,
+                };
+                spec.group = profile.group.clone();
+                profiles.insert(addr, profile.clone());
+                specs.push(spec);
+            }
+        }
+
+        Ok(Self {
+            specs,
+            profiles,
+            seed: file.seed.unwrap_or_else(random),
+            start: Instant::now(),
+        })
+    }
+
+    /// Create a [SimProber] for the target at `addr`. Targets not in the profile
+    /// (eg. added interactively) get the default behaviour.
+    pub fn prober(&self, addr: &IpAddr) -> SimProber {
+        let profile: Arc<SimProfile> = match self.profiles.get(addr) {
+            Some(p) => p.clone(),
+            None => SimProfile::default().into(),
+        };
+        // each target gets its own, but repeatable, random sequence
+        let bits: u64 = match addr {
+            IpAddr::V4(a) => u32::from(*a) as u64,
+            IpAddr::V6(a) => u128::from(*a) as u64,
+        };
+        SimProber {
+            profile,
+            rng: Mutex::new(StdRng::seed_from_u64(self.seed ^ bits)),
+            start: self.start,
+        }
+    }
+}
+
+/// [Prober] for a simulated target, behaving according to its [SimProfile].
+pub(crate) struct SimProber {
+    profile: Arc<SimProfile>,
+    rng: Mutex<StdRng>,
+    start: Instant,
+}
+
+impl SimProber {
+    /// Outcome of a probe sent at `secs` since the start of the simulation.
+    pub fn step(&self, secs: f64) -> MockStep {
+        let mut rng = self.rng.lock();
+        if self.profile.is_down(secs) || rng.random::<f64>() < self.profile.loss {
+            return MockStep::Loss;
+        }
+        let rtt: Duration = Duration::from_secs_f64(self.profile.sample_rtt(&mut *rng) / 1e3);
+        match self.profile.closed {
+            true => MockStep::Closed(rtt),
+            false => MockStep::Reply(rtt),
+        }
+    }
+}
+
+impl Prober for SimProber {
+    fn probe(
+        &self,
+        _addr: IpAddr,
+        _seq: u16,
+        _payload: Arc<[u8]>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, ProbeResult> {
+        let step: MockStep = self.step(self.start.elapsed().as_secs_f64());
+        play_step(step, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE: &str = r#"
+        seed = 7
+
+        [[target]]
+        name = "web"
+        group = "dc1"
+        count = 3
+        latency = 10.0
+        jitter = 5.0
+        distribution = "uniform"
+
+        [[target]]
+        outages = [[60, 90]]
+        flap = [10, 5]
+    "#;
+
+    #[test]
+    fn test_load_profile() {
+        let sim: Simulation = Simulation::from_toml(PROFILE).unwrap();
+        assert_eq!(sim.specs.len(), 4);
+        assert_eq!(sim.specs[0].addr, "198.18.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(sim.specs[2].label.as_deref(), Some("web-3"));
+        assert_eq!(sim.specs[2].group.as_deref(), Some("dc1"));
+        assert_eq!(sim.specs[3].label, None);
+
+        assert!(Simulation::from_toml("").is_err());
+        assert!(Simulation::from_toml("[[target]]\nloss = 2.0").is_err());
+        assert!(Simulation::from_toml("[[target]]\noutages = [[5, 1]]").is_err());
+        assert!(Simulation::from_toml("[[target]]\ncolor = 1").is_err());
+    }
+
+    #[test]
+    fn test_profile_behaviour() {
+        let sim: Simulation = Simulation::from_toml(PROFILE).unwrap();
+        let web: &SimProfile = &sim.profiles[&sim.specs[0].addr];
+        let mut rng: StdRng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let rtt: f64 = web.sample_rtt(&mut rng);
+            assert!((5.0..=15.0).contains(&rtt));
+        }
+
+        let other: &SimProfile = &sim.profiles[&sim.specs[3].addr];
+        assert!(!other.is_down(5.0));
+        assert!(other.is_down(12.0)); // flap down
+        assert!(!other.is_down(16.0)); // next flap cycle
+        assert!(other.is_down(61.0)); // outage
+        assert!(other.is_down(89.9));
+    }
+
+    #[test]
+    fn test_prober_repeatable() {
+        let steps = |sim: &Simulation| -> Vec<MockStep> {
+            let prober: SimProber = sim.prober(&sim.specs[0].addr);
+            (0..50).map(|i| prober.step(i as f64)).collect()
+        };
+        let a: Vec<MockStep> = steps(&Simulation::from_toml(PROFILE).unwrap());
+        let b: Vec<MockStep> = steps(&Simulation::from_toml(PROFILE).unwrap());
+        assert_eq!(a, b);
+        assert!(a.iter().all(|s| matches!(s, MockStep::Reply(_))));
+    }
+}
@@ -0,0 +1,292 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Outgoing IP header options of ICMP probes: TTL (IPv4) / hop limit (IPv6), DSCP
+//! marking and the don't-fragment flag. Useful for testing QoS queues (eg. the same
+//! host with EF and best-effort markings side by side) and MTU paths.
+//!
+//! NOTE: the options are set on the ICMP sockets only, so targets file entries with
+//! `tcp=` or `udp=` are refused if they set any.
+
+use socket2::SockRef;
+use std::{
+    fmt::Display,
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+    str::FromStr,
+};
+use surge_ping::Client;
+
+/// Named DSCP code points (RFC 4594 and friends)
+const DSCP_NAMES: [(&str, u8); 22] = [
+    ("be", 0),
+    ("cs1", 8),
+    ("af11", 10),
+    ("af12", 12),
+    ("af13", 14),
+    ("cs2", 16),
+    ("af21", 18),
+    ("af22", 20),
+    ("af23", 22),
+    ("cs3", 24),
+    ("af31", 26),
+    ("af32", 28),
+    ("af33", 30),
+    ("cs4", 32),
+    ("af41", 34),
+    ("af42", 36),
+    ("af43", 38),
+    ("cs5", 40),
+    ("va", 44),
+    ("ef", 46),
+    ("cs6", 48),
+    ("cs7", 56),
+];
+
+/// A DSCP code point (the upper 6 bits of the IPv4 TOS / IPv6 traffic class byte).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Dscp(u8);
+
+impl Dscp {
+    /// Value of the whole TOS / traffic class byte (ECN bits zero).
+    #[inline]
+    pub fn tos(&self) -> u8 {
+        self.0 << 2
+    }
+}
+
+impl FromStr for Dscp {
+    type Err = String;
+
+    /// Parse a DSCP name (`be`, `cs0`-`cs7`, `af11`-`af43`, `va`, `ef`) or number 0-63.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s: String = s.trim().to_lowercase();
+        if s == "cs0" {
+            return Ok(Dscp(0));
+        }
+        if let Some((_, v)) = DSCP_NAMES.iter().find(|(name, _)| *name == s) {
+            return Ok(Dscp(*v));
+        }
+        match s.strip_prefix("dscp").unwrap_or(&s).parse::<u8>() {
+            Ok(v) if v < 64 => Ok(Dscp(v)),
+            _ => Err(format!(
+                "Invalid DSCP '{s}' (0-63 or a name like ef, af41, cs5, be)"
+            )),
+        }
+    }
+}
+
+impl Display for Dscp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match DSCP_NAMES.iter().find(|(_, v)| *v == self.0) {
+            Some((name, _)) => write!(f, "{name}"),
+            None => write!(f, "dscp{}", self.0),
+        }
+    }
+}
+
+/// Outgoing IP header options. `None` means "use the value from the level above"
+/// (target -> command line -> kernel default).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct SockOpts {
+    /// TTL (IPv4) or hop limit (IPv6)
+    pub ttl: Option<u8>,
+    pub dscp: Option<Dscp>,
+    /// Don't-fragment flag (IPv4), or no local fragmentation (IPv6)
+    pub df: Option<bool>,
+}
+
+impl SockOpts {
+    /// Whether nothing is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill in the values not set here from `lower`.
+    pub fn or(&self, lower: &SockOpts) -> SockOpts {
+        SockOpts {
+            ttl: self.ttl.or(lower.ttl),
+            dscp: self.dscp.or(lower.dscp),
+            df: self.df.or(lower.df),
+        }
+    }
+
+    /// Set an option by name (as in the targets file), eg. `set("dscp", "ef")`.
+    /// Returns `Ok(false)` if `key` is not a socket option name.
+    pub fn set(&mut self, key: &str, val: &str) -> Result<bool, String> {
+        match key {
+            "ttl" => self.ttl = Some(parse_ttl(val)?),
+            "dscp" => self.dscp = Some(val.parse::<Dscp>()?),
+            "df" => {
+                self.df = Some(match val.to_lowercase().as_str() {
+                    "yes" | "on" | "true" | "1" => true,
+                    "no" | "off" | "false" | "0" => false,
+                    _ => return Err(format!("Invalid df value '{val}' (yes/no)")),
+                })
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Apply the options to the socket of `client`, see [SockOpts::apply_to].
+    pub fn apply(&self, client: &Client, v6: bool) -> io::Result<()> {
+        let raw: RawFd = client.get_socket().as_raw_fd();
+        // SAFETY: the socket is owned by `client`, which outlives this borrow.
+        // surge-ping exposes the raw descriptor only.
+        let fd: BorrowedFd<'_> = unsafe { BorrowedFd::borrow_raw(raw) };
+        self.apply_to(&fd, v6)
+    }
+
+    /// Apply the options to `socket`. Options not set are left at the kernel defaults.
+    pub fn apply_to<S: AsFd>(&self, socket: &S, v6: bool) -> io::Result<()> {
+        let sock: SockRef<'_> = SockRef::from(socket);
+        if let Some(t) = self.ttl {
+            match v6 {
+                false => sock.set_ttl(t as u32)?,
+                true => sock.set_unicast_hops_v6(t as u32)?,
+            }
+        }
+        if let Some(d) = self.dscp {
+            match v6 {
+                false => sock.set_tos(d.tos() as u32)?,
+                true => sock.set_tclass_v6(d.tos() as u32)?,
+            }
+        }
+        if let Some(on) = self.df {
+            set_df(socket.as_fd(), v6, on)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SockOpts {
+    /// Compact form for the protocol column, eg. `ef ttl=5 df`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        if let Some(d) = self.dscp {
+            parts.push(d.to_string());
+        }
+        if let Some(t) = self.ttl {
+            parts.push(format!("ttl={t}"));
+        }
+        match self.df {
+            Some(true) => parts.push("df".to_string()),
+            Some(false) => parts.push("df=no".to_string()),
+            None => {}
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Parse an outgoing TTL / hop limit 1-255.
+pub(crate) fn parse_ttl(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        Ok(t) if t >= 1 => Ok(t),
+        _ => Err(format!("Invalid TTL '{s}' (1-255)")),
+    }
+}
+
+/// Set the don't-fragment flag, which socket2 has no setter for.
+fn set_df(fd: BorrowedFd<'_>, v6: bool, on: bool) -> io::Result<()> {
+    let (level, name, val): (libc::c_int, libc::c_int, libc::c_int) = match (v6, on) {
+        (false, true) => (
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DO,
+        ),
+        (false, false) => (
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DONT,
+        ),
+        (true, on) => (libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG, on as libc::c_int),
+    };
+    let ret: libc::c_int = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            level,
+            name,
+            &val as *const libc::c_int as *const libc::c_void,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn test_dscp() {
+        assert_eq!("ef".parse::<Dscp>(), Ok(Dscp(46)));
+        assert_eq!("AF41".parse::<Dscp>(), Ok(Dscp(34)));
+        assert_eq!("cs0".parse::<Dscp>(), Ok(Dscp(0)));
+        assert_eq!("7".parse::<Dscp>(), Ok(Dscp(7)));
+        assert_eq!("dscp7".parse::<Dscp>(), Ok(Dscp(7)));
+        assert!("64".parse::<Dscp>().is_err());
+        assert!("af44".parse::<Dscp>().is_err());
+        assert_eq!(Dscp(46).tos(), 0xb8);
+        assert_eq!(
+            (Dscp(0).to_string(), Dscp(7).to_string()),
+            ("be".into(), "dscp7".into())
+        );
+    }
+
+    #[test]
+    fn test_sock_opts() {
+        let mut tgt: SockOpts = SockOpts::default();
+        assert!(tgt.is_empty());
+        assert_eq!(tgt.set("dscp", "ef"), Ok(true));
+        assert_eq!(tgt.set("df", "no"), Ok(true));
+        assert_eq!(tgt.set("label", "x"), Ok(false));
+        assert!(tgt.set("ttl", "0").is_err());
+        assert!(tgt.set("df", "maybe").is_err());
+
+        let global: SockOpts = SockOpts {
+            ttl: Some(5),
+            dscp: Some(Dscp(0)),
+            df: Some(true),
+        };
+        let eff: SockOpts = tgt.or(&global);
+        assert_eq!(
+            (eff.ttl, eff.dscp, eff.df),
+            (Some(5), Some(Dscp(46)), Some(false))
+        );
+        assert_eq!(eff.to_string(), "ef ttl=5 df=no");
+        assert_eq!(global.to_string(), "be ttl=5 df");
+    }
+
+    #[test]
+    fn test_apply_sock_opts() {
+        let opts: SockOpts = SockOpts {
+            ttl: Some(5),
+            dscp: "ef".parse().ok(),
+            df: Some(true),
+        };
+        let sock: UdpSocket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        opts.apply_to(&sock, false).unwrap();
+        let sock: SockRef<'_> = SockRef::from(&sock);
+        assert_eq!((sock.ttl().unwrap(), sock.tos().unwrap()), (5, 0xb8));
+
+        // IPv6 may not be available
+        if let Ok(sock) = UdpSocket::bind("[::1]:0") {
+            opts.apply_to(&sock, true).unwrap();
+            let sock: SockRef<'_> = SockRef::from(&sock);
+            assert_eq!(sock.unicast_hops_v6().unwrap(), 5);
+            assert_eq!(sock.tclass_v6().unwrap(), 0xb8);
+        }
+
+        // untouched options stay at the kernel defaults
+        let sock: UdpSocket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let ttl: u32 = SockRef::from(&sock).ttl().unwrap();
+        SockOpts::default().apply_to(&sock, false).unwrap();
+        assert_eq!(SockRef::from(&sock).ttl().unwrap(), ttl);
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Status state machine of a target: every change of [PingStatus] is recorded as a
+//! [Transition] with a timestamp and the reason, and the time spent in each state
+//! is accounted for.
+
+use crate::{structs::PingStatus, utils::local_time_str};
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Maximum number of transitions kept in the log (the counters cover all of them)
+const TRANSITION_LOG_MAX: usize = 200;
+
+/// State of the status machine: [PingStatus] without the error details.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum StatusKind {
+    Ok,
+    Closed,
+    Timeout,
+    NotReachable,
+    Error,
+    Laggy,
+    Lossy,
+    Flappy,
+    RouteChange,
+    Paused,
+    #[default]
+    None,
+}
+
+impl StatusKind {
+    /// All states, in the order of their time counters.
+    pub const ALL: [StatusKind; 11] = [
+        StatusKind::Ok,
+        StatusKind::Closed,
+        StatusKind::Timeout,
+        StatusKind::NotReachable,
+        StatusKind::Error,
+        StatusKind::Laggy,
+        StatusKind::Lossy,
+        StatusKind::Flappy,
+        StatusKind::RouteChange,
+        StatusKind::Paused,
+        StatusKind::None,
+    ];
+
+    /// Short name as shown in the status column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusKind::Ok => "OK",
+            StatusKind::Closed => "closed",
+            StatusKind::Timeout => "timeout",
+            StatusKind::NotReachable => "unreach",
+            StatusKind::Error => "error",
+            StatusKind::Laggy => "laggy",
+            StatusKind::Lossy => "lossy",
+            StatusKind::Flappy => "flapping",
+            StatusKind::RouteChange => "rerouted",
+            StatusKind::Paused => "paused",
+            StatusKind::None => "-",
+        }
+    }
+}
+
+impl Display for StatusKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl From<&PingStatus> for StatusKind {
+    fn from(s: &PingStatus) -> Self {
+        match s {
+            PingStatus::Ok => StatusKind::Ok,
+            PingStatus::Closed => StatusKind::Closed,
+            PingStatus::Timeout => StatusKind::Timeout,
+            PingStatus::NotReachable => StatusKind::NotReachable,
+            PingStatus::Error(_) => StatusKind::Error,
+            PingStatus::Laggy => StatusKind::Laggy,
+            PingStatus::Lossy => StatusKind::Lossy,
+            PingStatus::Flappy => StatusKind::Flappy,
+            PingStatus::RouteChange => StatusKind::RouteChange,
+            PingStatus::Paused => StatusKind::Paused,
+            PingStatus::None => StatusKind::None,
+        }
+    }
+}
+
+/// A single status change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Transition {
+    pub at: SystemTime,
+    pub from: StatusKind,
+    pub to: StatusKind,
+    /// What triggered the change, eg. `6 of last 10 probes lost`
+    pub reason: String,
+}
+
+impl Display for Transition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} -> {} ({})",
+            local_time_str(self.at),
+            self.from,
+            self.to,
+            self.reason
+        )
+    }
+}
+
+/// Per-target status state machine.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct StatusMachine {
+    state: StatusKind,
+    /// When the current state was entered, `None` before the first transition
+    since: Option<Instant>,
+    /// Time spent in each state (see [StatusKind::ALL]), excluding the current stint
+    time_in: [Duration; StatusKind::ALL.len()],
+    changes: u64,
+    /// Recent transitions, oldest first
+    log: VecDeque<Transition>,
+}
+
+impl StatusMachine {
+    /// Current state.
+    #[inline]
+    pub fn state(&self) -> StatusKind {
+        self.state
+    }
+
+    /// When the current state was entered.
+    #[inline]
+    pub fn since(&self) -> Option<Instant> {
+        self.since
+    }
+
+    /// Total number of transitions.
+    #[inline]
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    /// Logged transitions, oldest first.
+    pub fn log(&self) -> &VecDeque<Transition> {
+        &self.log
+    }
+
+    /// Move to state `to` at `now` (wall-clock time `at`), recording the transition
+    /// with `reason`. Returns `false` if already in that state.
+    pub fn transition(
+        &mut self,
+        to: StatusKind,
+        reason: String,
+        now: Instant,
+        at: SystemTime,
+    ) -> bool {
+        if to == self.state && self.since.is_some() {
+            return false;
+        }
+        if let Some(since) = self.since {
+            self.time_in[self.index()] += now.saturating_duration_since(since);
+        }
+        if self.log.len() == TRANSITION_LOG_MAX {
+            self.log.pop_front();
+        }
+        self.log.push_back(Transition {
+            at,
+            from: self.state,
+            to,
+            reason,
+        });
+        self.changes += 1;
+        self.state = to;
+        self.since = Some(now);
+        true
+    }
+
+    #[inline]
+    fn index(&self) -> usize {
+        StatusKind::ALL
+            .iter()
+            .position(|k: &StatusKind| *k == self.state)
+            .unwrap_or_default()
+    }
+
+    /// Time spent in each state as of `now`, including the ongoing stint. States
+    /// never entered are left out.
+    pub fn time_in_state(&self, now: Instant) -> Vec<(StatusKind, Duration)> {
+        let mut times: [Duration; StatusKind::ALL.len()] = self.time_in;
+        if let Some(since) = self.since {
+            times[self.index()] += now.saturating_duration_since(since);
+        }
+        StatusKind::ALL
+            .into_iter()
+            .zip(times)
+            .filter(|(_, d)| !d.is_zero())
+            .collect()
+    }
+
+    /// One-line summary of the time in each state, eg.
+    /// `OK 97.5%, timeout 2.5% (4 changes)`.
+    pub fn summary(&self, now: Instant) -> String {
+        let times: Vec<(StatusKind, Duration)> = self.time_in_state(now);
+        let total: f64 = times.iter().map(|(_, d)| d.as_secs_f64()).sum();
+        let shares: Vec<String> = times
+            .iter()
+            .filter(|_| total > 0.0)
+            .map(|(k, d)| format!("{k} {:.1}%", 1e2 * d.as_secs_f64() / total))
+            .collect();
+        match shares.is_empty() {
+            true => format!("{} ({} changes)", self.state, self.changes),
+            false => format!("{} ({} changes)", shares.join(", "), self.changes),
+        }
+    }
+
+    /// Forget everything.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_machine() {
+        let t0: Instant = Instant::now();
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+        let wall: SystemTime = SystemTime::UNIX_EPOCH;
+        let mut sm: StatusMachine = StatusMachine::default();
+
+        assert!(sm.transition(StatusKind::Ok, "reply".into(), at(0), wall));
+        assert!(!sm.transition(StatusKind::Ok, "reply".into(), at(5), wall));
+        assert!(sm.transition(StatusKind::Laggy, "slow".into(), at(30), wall));
+        assert!(sm.transition(StatusKind::Ok, "reply".into(), at(40), wall));
+        assert_eq!(sm.changes(), 3);
+        assert_eq!(sm.since(), Some(at(40)));
+        assert_eq!(sm.state(), StatusKind::Ok);
+
+        let last: &Transition = sm.log().back().unwrap();
+        assert_eq!((last.from, last.to), (StatusKind::Laggy, StatusKind::Ok));
+        assert_eq!(
+            sm.log()[0],
+            Transition {
+                at: wall,
+                from: StatusKind::None,
+                to: StatusKind::Ok,
+                reason: "reply".to_string(),
+            }
+        );
+
+        assert_eq!(
+            sm.time_in_state(at(50)),
+            vec![
+                (StatusKind::Ok, Duration::from_secs(40)),
+                (StatusKind::Laggy, Duration::from_secs(10)),
+            ]
+        );
+        assert_eq!(sm.summary(at(50)), "OK 80.0%, laggy 20.0% (3 changes)");
+
+        sm.clear();
+        assert_eq!(sm.summary(at(50)), "- (0 changes)");
+    }
+}
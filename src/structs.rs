@@ -4,44 +4,96 @@
 
 use crate::{
     args::MpConfig,
+    bursts::LossBursts,
+    columns::{Column, DEFAULT_COLUMNS, header_row},
+    errors::ErrorCounts,
+    icmperr::{IcmpErrorListener, IcmpReport},
+    ident::{ClientShard, IdLease, IdPool},
+    ip_addresses::AddrFamily,
     latencywin::LatencyWindow,
-    tui::{AppLayout, TableRow},
+    outage::{OutageStats, OutageTracker},
+    probe::{ProbeError, Proto},
+    rules::PendingStatus,
+    sockopts::SockOpts,
+    status::{StatusKind, StatusMachine},
+    targets::{TargetOpts, TargetSpec, target_key},
+    timewin::{StatWindow, TimeWindows, WindowStats},
+    ttl::TtlTracker,
+    tui::{AppLayout, Prompt, TableRow},
     utils::nice_permission_error,
+    voip::{Codec, VoipScore},
 };
 use itertools::Itertools;
 use miniutils::ProcessInfo;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use ratatui::{
+    style::{Style, Stylize},
+    widgets::TableState,
+};
+use socket2::Type;
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, HashSet, VecDeque},
     fmt::Display,
-    net::IpAddr,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     ops::Index,
     sync::{
         Arc,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
-use surge_ping::{Client, Config, ICMP, SurgeError};
+use surge_ping::{Client, Config, ICMP};
 
 const MICRO_TO_MILLI: f64 = 1e3;
 const DEFAULT_REFRESH: Duration = Duration::from_millis(250);
+const MESSAGE_TTL: Duration = Duration::from_secs(5);
 
 /// Main application state structure.
 pub(crate) struct AppState<'a> {
     pub pi: miniutils::ProcessInfo,
-    pub c_v4: Option<Arc<Client>>,
-    pub c_v6: Option<Arc<Client>>,
+    /// IPv4 and IPv6 clients by (is IPv6, socket options). More than one per key only
+    /// with datagram sockets, if a socket runs out of identifiers.
+    pub shards: HashMap<(bool, SockOpts), Vec<ClientShard>>,
+    /// ICMP identifiers in use by (is IPv6) with raw sockets, shared by all the clients
+    /// of the address family
+    pub idents: HashMap<bool, Arc<Mutex<IdPool>>>,
+    /// Listener for ICMP error replies (raw sockets only)
+    pub icmp_errors: Option<Arc<IcmpErrorListener>>,
     pub targets: Vec<Arc<PingTarget>>,
     pub tasks: Vec<tokio::task::JoinHandle<()>>,
     pub layout: RwLock<AppLayout>,
     pub title: Option<ratatui::text::Line<'a>>,
+    /// Statistics columns of the table
+    pub columns: Vec<Column>,
+    /// Time window of the windowed statistics columns
+    pub window: StatWindow,
     /// Table headers
     pub headers: TableRow,
     /// UI refresh interval
     pub ui_interval: Duration,
     /// Next scheduled UI refresh time
     pub ui_next_refresh: tokio::time::Instant,
+    /// Whether to show the target label column
+    pub labels: bool,
+    /// Whether to show the probe protocol column
+    pub protos: bool,
+    /// Whether unprivileged ICMP datagram sockets are used (forced or as fallback)
+    pub unprivileged: bool,
+    /// Address family preference for hostnames added interactively
+    pub family: AddrFamily,
+    /// Status bar message and the time it was set
+    pub message: Option<(String, Instant)>,
+    /// Active input prompt, if any
+    pub prompt: Option<Prompt>,
+    /// Target changes requested from the UI, to be applied by the main loop
+    pub pending: Vec<TargetCmd>,
+    /// Added targets still being resolved
+    pub lookups: Vec<TargetLookup>,
+    /// Targets added interactively. Kept over targets file reloads.
+    pub added: Vec<TargetSpec>,
+    /// Keys of targets removed interactively. Not brought back by targets file reloads.
+    pub removed: HashSet<String>,
     pub verbose: bool,
     pub debug: bool,
 }
@@ -50,38 +102,223 @@ impl AppState<'_> {
     /// Build the application state based on the provided configuration.
     /// - set up UI refresh interval
     /// - set up [surge_ping::Client] instances for IPv4 and IPv6 as needed
+    ///   (falling back to unprivileged sockets if necessary, see [AppState::new_client]),
+    ///   one for each distinct set of outgoing IP options
     ///
     /// NOTE: sharing a client across multiple targets is (async) safe
     /// and allows socket reuse.
     pub fn build(mut self, conf: &Arc<MpConfig>) -> Result<Self, Box<dyn std::error::Error>> {
         self.debug = conf.debug;
         self.verbose = conf.verbose;
+        self.unprivileged = conf.unprivileged;
+        self.family = conf.family();
         if self.ui_interval != DEFAULT_REFRESH {
             self.ui_interval = Duration::from_millis(conf.refresh);
         }
+        if !conf.columns.is_empty() {
+            self.columns = conf.columns.clone();
+        }
+        // Labels can only come from a targets file or a simulation profile
+        self.labels = conf.file.is_some() || conf.sim.is_some();
+        // Protocol column if anything else than ICMP is (or may be, after reload) in use,
+        // or targets have IP options of their own
+        self.protos = conf.file.is_some()
+            || conf.tcp.is_some()
+            || conf.udp.is_some()
+            || conf
+                .specs
+                .iter()
+                .any(|s: &TargetSpec| s.opts.proto.is_some() || !s.opts.sock.is_empty());
+        self.rebuild_headers();
+
+        // IPv4 & IPv6 clients, only needed for ICMP targets (and never when simulating)
+        let icmp: Vec<(IpAddr, SockOpts)> = conf
+            .specs
+            .iter()
+            .filter(|s: &&TargetSpec| conf.sim.is_none() && conf.proto(&s.opts) == Proto::Icmp)
+            .map(|s: &TargetSpec| match s.addr {
+                IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED.into(), conf.sock_opts(&s.opts)),
+                IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED.into(), conf.sock_opts(&s.opts)),
+            })
+            .unique()
+            .collect();
+        for (addr, opts) in icmp {
+            self.add_shard(&addr, opts)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Rebuild the table headers after a change of `columns`, `labels` or `protos`.
+    pub fn rebuild_headers(&mut self) {
+        self.headers = header_row(&self.columns, self.window);
         if self.debug {
             self.headers.add_item("Seq");
         }
+        if self.labels {
+            self.headers.insert_item(1, "Label");
+        }
+        if self.protos {
+            self.headers.insert_item(1, "Proto");
+        }
+        self.headers.set_style_all(Style::new().bold().yellow());
+        self.layout.get_mut().tbl_hdr_widths = self.headers.widths();
+    }
+
+    /// Switch the windowed statistics columns to the next [StatWindow].
+    pub fn cycle_window(&mut self) {
+        self.window = self.window.next();
+        self.rebuild_headers();
+        self.notify(format!("Statistics window: {}", self.window));
+    }
 
-        // IPv4 & IPv6 clients
-        self.c_v4 = if conf.addrs.iter().any(|a: &IpAddr| a.is_ipv4()) {
-            match Client::new(&Config::default()) {
-                Ok(c) => Arc::new(c).into(),
-                Err(e) => return Err(nice_permission_error(&e, "v4")),
+    /// Append `extra` statistics columns not already shown (eg. for the exit summary).
+    pub fn add_columns(&mut self, extra: &[Column]) {
+        for c in extra {
+            if !self.columns.contains(c) {
+                self.columns.push(*c);
             }
-        } else {
-            None
+        }
+        self.rebuild_headers();
+    }
+
+    /// Lease a unique ICMP identifier for a target at `addr`, on the first
+    /// [surge_ping::Client] of its address family and socket options `opts` with one
+    /// free. A new client (ie. socket) is opened if there is none, or with datagram
+    /// sockets if all identifiers are already in use or another target has the same
+    /// address (see [crate::ident]).
+    ///
+    /// A raw socket sees every echo reply of its address family, so identifiers are
+    /// unique across all raw clients of the family and another socket would not keep
+    /// them apart. Once they are all in use, no more ICMP targets can be added.
+    ///
+    /// NOTE: the address is the one at lease time. A hostname target re-resolved to the
+    /// address of another target keeps its socket.
+    pub fn lease_ident(
+        &mut self,
+        addr: &IpAddr,
+        opts: SockOpts,
+    ) -> Result<(Arc<Client>, IdLease), Box<dyn std::error::Error>> {
+        let per_dest: bool = self.unprivileged;
+        let free = |s: &ClientShard| {
+            s.lease(*addr, per_dest)
+                .map(|l: IdLease| (s.client.clone(), l))
         };
-        self.c_v6 = if conf.addrs.iter().any(|a: &IpAddr| a.is_ipv6()) {
-            match Client::new(&Config::builder().kind(ICMP::V6).build()) {
-                Ok(c) => Arc::new(c).into(),
-                Err(e) => return Err(nice_permission_error(&e, "v6")),
+        if let Some(shards) = self.shards.get(&(addr.is_ipv6(), opts))
+            && let Some(leased) = shards.iter().find_map(free)
+        {
+            return Ok(leased);
+        }
+        let full = |ids: &Arc<Mutex<IdPool>>| ids.lock().is_full();
+        if !self.unprivileged && self.idents.get(&addr.is_ipv6()).is_some_and(full) {
+            let ver: &str = match addr {
+                IpAddr::V4(_) => "v4",
+                IpAddr::V6(_) => "v6",
+            };
+            return Err(format!(
+                "All ICMP identifiers of IP{ver} are in use, cannot add more ICMP targets"
+            )
+            .into());
+        }
+        let why: &str = match self.shards.get(&(addr.is_ipv6(), opts)) {
+            Some(shards) if shards.iter().any(|s: &ClientShard| !s.is_full()) => {
+                "another target has the same address"
             }
-        } else {
-            None
+            _ => "ICMP identifiers exhausted",
         };
+        let shard: &ClientShard = self.add_shard(addr, opts)?;
+        let leased: (Arc<Client>, IdLease) =
+            free(shard).ok_or("No ICMP identifiers left on a new socket")?;
+        let n: usize = self.shards[&(addr.is_ipv6(), opts)].len();
+        if n > 1 {
+            let ver: &str = match addr {
+                IpAddr::V4(_) => "v4",
+                IpAddr::V6(_) => "v6",
+            };
+            self.notify(format!("{why}, opening IP{ver} socket #{n}"));
+        }
+        Ok(leased)
+    }
 
-        Ok(self)
+    /// Open a new [surge_ping::Client] for the address family of `addr`, with the
+    /// outgoing IP options `opts` set on its socket.
+    fn add_shard(
+        &mut self,
+        addr: &IpAddr,
+        opts: SockOpts,
+    ) -> Result<&ClientShard, Box<dyn std::error::Error>> {
+        let (kind, ver) = match addr {
+            IpAddr::V4(_) => (ICMP::V4, "v4"),
+            IpAddr::V6(_) => (ICMP::V6, "v6"),
+        };
+        let client: Client = self.new_client(kind, ver)?;
+        opts.apply(&client, addr.is_ipv6())
+            .map_err(|e| format!("Cannot set '{opts}' on an IP{ver} socket: {e}"))?;
+        // Datagram sockets have identifiers of their own (see [crate::ident])
+        let ids: Arc<Mutex<IdPool>> = match self.unprivileged {
+            true => Arc::default(),
+            false => self.idents.entry(addr.is_ipv6()).or_default().clone(),
+        };
+        let shard: ClientShard = ClientShard::new(client, ids);
+        // ICMP errors need a raw socket of their own, so only with raw clients
+        if !self.unprivileged && self.icmp_errors.is_none() {
+            self.icmp_errors = IcmpErrorListener::start().ok();
+        }
+        let shards: &mut Vec<ClientShard> = self.shards.entry((addr.is_ipv6(), opts)).or_default();
+        shards.push(shard);
+        Ok(shards.last().unwrap())
+    }
+
+    /// Create a new [surge_ping::Client]. Raw sockets are used unless unprivileged mode
+    /// is set. If raw sockets are denied (no `CAP_NET_RAW`), unprivileged ICMP datagram
+    /// sockets are tried instead, which Linux allows for group IDs within
+    /// `net.ipv4.ping_group_range` (for both IPv4 and IPv6).
+    fn new_client(&mut self, kind: ICMP, ver: &str) -> Result<Client, Box<dyn std::error::Error>> {
+        let config = |t: Type| Config::builder().kind(kind).sock_type_hint(t).build();
+
+        if !self.unprivileged {
+            match Client::new(&config(Type::RAW)) {
+                Ok(c) => {
+                    // surge-ping may already have fallen back to a datagram socket by itself
+                    self.unprivileged = c.get_socket().get_type() == Type::DGRAM;
+                    return Ok(c);
+                }
+                Err(e) if e.kind() != ErrorKind::PermissionDenied => {
+                    return Err(nice_permission_error(&e, ver));
+                }
+                Err(_) => {}
+            }
+        }
+
+        match Client::new(&config(Type::DGRAM)) {
+            Ok(c) => {
+                self.unprivileged = true;
+                Ok(c)
+            }
+            Err(e) => Err(nice_permission_error(&e, ver)),
+        }
+    }
+
+    /// Show a message in the status bar for a few seconds.
+    pub fn notify<S: Into<String>>(&mut self, msg: S) {
+        self.message = Some((msg.into(), Instant::now()));
+    }
+
+    /// Current status bar message, if it hasn't expired yet.
+    pub fn status_message(&self) -> Option<&str> {
+        match &self.message {
+            Some((msg, at)) if at.elapsed() < MESSAGE_TTL => Some(msg),
+            _ => None,
+        }
+    }
+
+    /// Keep the table selection within the target list after targets were removed.
+    pub fn clamp_selection(&mut self) {
+        let last: Option<usize> = self.targets.len().checked_sub(1);
+        let ts: &mut TableState = &mut self.layout.get_mut().tablestate;
+        if let Some(sel) = ts.selected() {
+            ts.select(last.map(|l: usize| sel.min(l)));
+        }
     }
 
     /// Pause pinging for the target at the specified index.
@@ -96,34 +333,61 @@ impl Default for AppState<'_> {
     fn default() -> Self {
         Self {
             pi: ProcessInfo::new(),
-            c_v4: None,
-            c_v6: None,
+            shards: HashMap::new(),
+            idents: HashMap::new(),
+            icmp_errors: None,
             targets: vec![],
             tasks: vec![],
             layout: AppLayout::default().into(),
             title: None,
-            headers: TableRow::from_iter([
-                "Address", "Sent", "Recv", "Loss", "Last", "Mean", "Min", "Max", "Stdev", "Status",
-            ]),
+            columns: DEFAULT_COLUMNS.to_vec(),
+            window: StatWindow::All,
+            headers: header_row(&DEFAULT_COLUMNS, StatWindow::All),
             ui_interval: DEFAULT_REFRESH,
             ui_next_refresh: tokio::time::Instant::now(),
+            labels: false,
+            protos: false,
+            unprivileged: false,
+            family: AddrFamily::default(),
+            message: None,
+            prompt: None,
+            pending: vec![],
+            lookups: vec![],
+            added: vec![],
+            removed: HashSet::new(),
             verbose: false,
             debug: false,
         }
     }
 }
 
+/// Target set changes requested interactively.
+#[derive(Debug)]
+pub(crate) enum TargetCmd {
+    /// Target(s) as typed in the prompt, with the same syntax as on the command line
+    Add(String),
+    Remove(Arc<PingTarget>),
+}
+
+/// Background expansion of a target typed in the prompt: the input and its target
+/// specs, or the reason it can't be added.
+pub(crate) type TargetLookup = tokio::task::JoinHandle<(String, Result<Vec<TargetSpec>, String>)>;
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Debug, Default)]
 pub(crate) enum PingStatus {
     Ok,
+    /// Host answered, but the probed port is closed (TCP RST or ICMP port unreachable)
+    Closed,
     Timeout,
     NotReachable,
-    Error(SurgeError),
+    Error(ProbeError),
     Laggy,
     Lossy,
     Flappy,
+    /// Reply TTL changed recently, ie. probably a route change
+    RouteChange,
     Paused,
     #[default]
     None,
@@ -132,15 +396,9 @@ pub(crate) enum PingStatus {
 impl Display for PingStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            PingStatus::Ok => write!(f, "OK"),
-            PingStatus::Timeout => write!(f, "timeout"),
-            PingStatus::NotReachable => write!(f, "unreach"),
-            PingStatus::Error(_) => write!(f, "error"),
-            PingStatus::Laggy => write!(f, "laggy"),
-            PingStatus::Lossy => write!(f, "lossy"),
-            PingStatus::Flappy => write!(f, "flapping"),
-            PingStatus::Paused => write!(f, "paused"),
-            PingStatus::None => write!(f, "-"),
+            // eg. "unreach (host) from 10.0.0.1"
+            PingStatus::Error(ProbeError::IcmpError(r)) => write!(f, "{r}"),
+            s => write!(f, "{}", StatusKind::from(s)),
         }
     }
 }
@@ -149,18 +407,89 @@ impl Display for PingStatus {
 pub(crate) struct PingTargetInner {
     pub sent: u64,
     pub recv: u64,
+    /// Timed out probes answered within the grace period (not included in `recv`)
+    pub late: u64,
+    /// Duplicate replies (`DUP!`), on top of the first reply to a probe
+    pub dups: u64,
+    /// Replies which arrived after the reply to a later probe
+    pub reordered: u64,
+    /// Highest sequence number answered so far, to spot reordered replies
+    max_reply_seq: Option<u64>,
     pub rtts: LatencyWindow, // RTTs in microseconds (rolling window)
+    /// Replies which only tell the port is closed (also included in `recv`)
+    pub recv_closed: u64,
+    /// RTTs of "port closed" replies, kept apart from the actual replies in `rtts`
+    pub closed_rtts: LatencyWindow,
     /// Detailed history of recent sent/received packets
     pub recent: PacketHistory,
     pub status: PingStatus,
+    /// Status transitions and time spent in each status
+    pub machine: StatusMachine,
+    /// Status waiting for the dwell time to pass, see [dwell_filter](crate::rules::dwell_filter)
+    pub pending: PendingStatus,
     /// Authoritative last sent sequence number
-    pub last_seq: u16,
+    pub last_seq: u64,
     /// Authoritative last sent timestamp. Will be slightly before actual send time. The
     /// difference can be calculated from [PacketRecord] (with the same sequence number).
     pub last_sent: Option<Instant>,
+    /// Interarrival jitter estimate (µs) as per RFC 3550, see [PingTargetInner::update_jitter]
+    pub jitter: Option<f64>,
+    /// RTT of the previous reply for the jitter calculation
+    prev_rtt: Option<u32>,
+    /// Loss and RTT over the last 1, 5 and 15 minutes
+    pub windows: TimeWindows,
+    /// Outages (N consecutive losses) and their statistics
+    pub outages: OutageTracker,
+    /// Run lengths of consecutive losses over the whole run
+    pub bursts: LossBursts,
+    /// Failed probes by cause
+    pub errors: ErrorCounts,
+    /// Latest ICMP error reply and when it was received
+    pub icmp_error: Option<(IcmpReport, SystemTime)>,
+    /// Reply TTL and its changes
+    pub ttl: TtlTracker,
 }
 
 impl PingTargetInner {
+    /// The RTT window for display and status purposes: actual replies if there
+    /// are any, otherwise "port closed" replies (which still tell the host is up).
+    #[inline]
+    pub fn rtt_window(&self) -> &LatencyWindow {
+        match self.rtts.is_empty() {
+            true => &self.closed_rtts,
+            false => &self.rtts,
+        }
+    }
+
+    /// Set the status, recording a transition with `reason` if it changes.
+    pub fn set_status(&mut self, status: PingStatus, reason: String, now: Instant) {
+        self.machine
+            .transition((&status).into(), reason, now, SystemTime::now());
+        self.status = status;
+    }
+
+    /// Update the jitter estimate with the RTT of a new reply, in the order the
+    /// replies arrive. RFC 3550 (section 6.4.1) defines it from the differences in
+    /// transit time of successive packets, smoothed with a gain of 1/16:
+    /// `J += (|D| - J) / 16`. The RTT serves as the transit time here.
+    pub fn update_jitter(&mut self, rtt: u32) {
+        if let Some(prev) = self.prev_rtt {
+            let d: f64 = (rtt as f64 - prev as f64).abs();
+            let j: f64 = self.jitter.unwrap_or(0.0);
+            self.jitter = Some(j + (d - j) / 16.0);
+        }
+        self.prev_rtt = Some(rtt);
+    }
+
+    /// Note a reply to probe `seq`, counting it as reordered if a later probe has
+    /// already been answered.
+    pub fn check_reply_order(&mut self, seq: u64) {
+        match self.max_reply_seq {
+            Some(max) if seq < max => self.reordered += 1,
+            _ => self.max_reply_seq = Some(seq),
+        }
+    }
+
     #[inline]
     pub fn is_lossy(&self, n: usize, threshold: f64) -> bool {
         self.recent.recent_losses(n) as f64 / n as f64 >= threshold
@@ -171,9 +500,11 @@ impl PingTargetInner {
         self.recent.recent_transitions(n) >= threshold
     }
 
+    /// Whether the recent replies are laggy. "Port closed" replies are left out on
+    /// both sides of the comparison, like they are from the RTT statistics.
     #[inline]
     pub fn is_laggy(&self, n: usize, threshold: f64) -> Result<bool, String> {
-        let long_mean: f64 = self.rtts.mean().map(|m: f64| m).unwrap_or(0.0);
+        let long_mean: f64 = self.rtts.mean().unwrap_or(0.0);
         let recent_mean: Duration = self.recent.mean(Some(n))?;
         Ok(recent_mean.as_micros() as f64 > long_mean * threshold)
     }
@@ -181,26 +512,96 @@ impl PingTargetInner {
 
 #[derive(Debug)]
 pub(crate) struct PingTarget {
-    pub addr: IpAddr,
+    /// Hostname if the target was given by name (re-resolved periodically)
+    pub host: Option<String>,
+    pub label: Option<String>,
+    pub group: Option<String>,
+    /// Per-target overrides of global options
+    pub opts: TargetOpts,
     pub data: RwLock<PingTargetInner>,
+    addr: RwLock<IpAddr>,
+    addr_changes: AtomicU32,
     paused: AtomicBool,
+    stopped: AtomicBool,
 }
 
 impl PingTarget {
     /// Create a new [PingTarget] for the specified IP address.
     ///
     /// - `histsize` specifies the size of the full RTT latency window.
-    /// - `detailed` specifies the number of more detailed recent packet stats to keep.
+    /// - `detailed` specifies the number of more detailed recent packet stats to keep,
+    ///   and the size of the "port closed" RTT window.
     pub fn new(addr: IpAddr, histsize: usize, detailed: usize) -> Self {
         Self {
-            addr,
+            host: None,
+            label: None,
+            group: None,
+            opts: TargetOpts::default(),
             data: PingTargetInner {
                 rtts: LatencyWindow::new(histsize),
+                closed_rtts: LatencyWindow::new(detailed),
                 recent: PacketHistory::new(detailed),
                 ..Default::default()
             }
             .into(),
+            addr: addr.into(),
+            addr_changes: AtomicU32::new(0),
             paused: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Create a new [PingTarget] from a [TargetSpec]. See [PingTarget::new] for the rest.
+    pub fn from_spec(spec: &TargetSpec, histsize: usize, detailed: usize) -> Self {
+        Self {
+            host: spec.host.clone(),
+            label: spec.label.clone(),
+            group: spec.group.clone(),
+            opts: spec.opts.clone(),
+            ..Self::new(spec.addr, histsize, detailed)
+        }
+    }
+
+    /// Current IP address of this target.
+    #[inline]
+    pub fn addr(&self) -> IpAddr {
+        *self.addr.read()
+    }
+
+    /// Switch to a new IP address (after re-resolution). No-op if the address is unchanged.
+    pub fn set_addr(&self, addr: IpAddr) {
+        let mut cur = self.addr.write();
+        if *cur != addr {
+            *cur = addr;
+            self.addr_changes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// How many times the address of this target has changed since startup.
+    #[inline]
+    pub fn addr_changes(&self) -> u32 {
+        self.addr_changes.load(Ordering::Relaxed)
+    }
+
+    /// Identity key of this target, see [TargetSpec::key].
+    pub fn key(&self) -> String {
+        target_key(self.host.as_deref(), self.addr(), &self.opts)
+    }
+
+    /// Whether this target was created from an equivalent [TargetSpec]. The resolved
+    /// address of hostname targets is ignored since it may change at runtime anyway.
+    pub fn matches(&self, spec: &TargetSpec) -> bool {
+        self.key() == spec.key()
+            && self.label == spec.label
+            && self.group == spec.group
+            && self.opts == spec.opts
+    }
+
+    /// Display name of this target: hostname if available, otherwise the IP address.
+    pub fn name(&self) -> String {
+        match &self.host {
+            Some(h) => h.clone(),
+            None => self.addr().to_string(),
         }
     }
 
@@ -209,11 +610,27 @@ impl PingTarget {
         let mut data = self.data.write();
         data.sent = 0;
         data.recv = 0;
+        data.late = 0;
+        data.dups = 0;
+        data.reordered = 0;
+        data.max_reply_seq = None;
         data.rtts.clear();
+        data.recv_closed = 0;
+        data.closed_rtts.clear();
         data.recent.clear();
         data.status = PingStatus::None;
+        data.machine.clear();
+        data.pending = None;
         data.last_seq = 0;
         data.last_sent = None;
+        data.jitter = None;
+        data.prev_rtt = None;
+        data.windows.clear(Instant::now());
+        data.outages.clear();
+        data.bursts.clear();
+        data.errors.clear();
+        data.icmp_error = None;
+        data.ttl.clear();
     }
 
     /// Whether pinging currently paused for this target is.
@@ -222,12 +639,26 @@ impl PingTarget {
         self.paused.load(Ordering::Relaxed)
     }
 
+    /// Stop all tasks of this target permanently (ie. the target is being removed).
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether this target has been stopped.
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
     /// Toggle paused state for this target.
     fn toggle_pause(&self) {
         let state: bool = self.paused.load(Ordering::Relaxed);
         self.paused.store(!state, Ordering::Relaxed);
         if !state {
-            self.data.write().status = PingStatus::Paused;
+            let reason: String = "paused by user".to_string();
+            self.data
+                .write()
+                .set_status(PingStatus::Paused, reason, Instant::now());
         }
     }
 
@@ -255,15 +686,24 @@ impl PingTarget {
 /// Record for a single sent/received packet.
 #[derive(Debug, Clone)]
 pub(crate) struct PacketRecord {
-    pub seq: u16,
+    /// Sequence number of the probe (the on-wire value is the lowest 16 bits)
+    pub seq: u64,
     pub sent: Instant,
     rtt: Option<Duration>,
+    /// ICMP error received instead of a reply
+    pub icmp_error: Option<IcmpReport>,
+    /// The reply came after the timeout, within the grace period
+    pub late: bool,
+    /// TTL (IPv4) or hop limit (IPv6) of the reply, if known
+    pub ttl: Option<u8>,
+    /// The reply only tells the port is closed
+    pub closed: bool,
 }
 
 impl PacketRecord {
     /// Create a new [PacketRecord] for a sent packet with the current timestamp.
     /// Receive timestamp must be set later when/if a response is received.
-    pub fn new(seq: u16) -> Self {
+    pub fn new(seq: u64) -> Self {
         Self {
             seq,
             ..Default::default()
@@ -308,6 +748,10 @@ impl Default for PacketRecord {
             seq: 0,
             sent: Instant::now(),
             rtt: None,
+            icmp_error: None,
+            late: false,
+            ttl: None,
+            closed: false,
         }
     }
 }
@@ -367,6 +811,14 @@ impl PacketHistory {
         self.records.back()
     }
 
+    /// Get the most recent [PacketRecord] of probe `seq` for updating, if still kept.
+    pub fn find_mut(&mut self, seq: u64) -> Option<&mut PacketRecord> {
+        self.records
+            .iter_mut()
+            .rev()
+            .find(|r: &&mut PacketRecord| r.seq == seq)
+    }
+
     /// Get an iterator over the records.
     #[inline]
     pub fn iter(&self) -> std::collections::vec_deque::Iter<'_, PacketRecord> {
@@ -451,6 +903,7 @@ impl PacketHistory {
     }
 
     /// Calculate the mean (average) RTT in the history (or given N-sized window).
+    /// "Port closed" replies are not included.
     pub fn mean(&self, n: Option<usize>) -> Result<Duration, String> {
         if self.is_empty() {
             return Err("No records to calculate mean RTT".to_string());
@@ -465,6 +918,7 @@ impl PacketHistory {
         let (sum, count) = self
             .iter()
             .skip(skip)
+            .filter(|rec| !rec.closed)
             .filter_map(|rec| rec.rtt().ok())
             .fold((Duration::ZERO, 0u32), |(s, c), rtt| (s + rtt, c + 1));
 
@@ -523,9 +977,9 @@ impl Index<usize> for PacketHistory {
 #[derive(Debug)]
 pub(crate) struct HistorySnapshot {
     /// Starting sequence number from historical data
-    pub start_seq: u16,
+    pub start_seq: u64,
     /// Ending sequence number from historical data
-    pub end_seq: u16,
+    pub end_seq: u64,
     pub gaps_in_seqs: bool,
     pub last_out_of_order: bool,
     pub recent_losses: usize,
@@ -541,11 +995,11 @@ impl HistorySnapshot {
         let inspect_win: usize = 10;
 
         let gaps_in_seqs: bool = {
-            let mut expected_seq: Option<u16> = None;
+            let mut expected_seq: Option<u64> = None;
             let mut gaps: bool = false;
             for rec in data.iter().rev().take(inspect_win) {
                 if let Some(exp) = expected_seq {
-                    if rec.seq.wrapping_add(1) != exp {
+                    if rec.seq + 1 != exp {
                         gaps = true;
                         break;
                     }
@@ -556,8 +1010,8 @@ impl HistorySnapshot {
         };
 
         let last_out_of_order: bool = if data.len() >= 2 {
-            let last: u16 = data.last().unwrap().seq;
-            let second_last: u16 = data.iter().rev().nth(1).unwrap().seq;
+            let last: u64 = data.last().unwrap().seq;
+            let second_last: u64 = data.iter().rev().nth(1).unwrap().seq;
             last < second_last
         } else {
             false
@@ -605,18 +1059,43 @@ impl HistorySnapshot {
 pub(crate) struct StatsSnapshot {
     pub sent: u64,
     pub recv: u64,
+    /// Late replies, ie. answered after the timeout (not included in `recv`)
+    pub late: u64,
+    /// Duplicate replies over the whole run
+    pub dups: u64,
+    /// Reordered replies over the whole run
+    pub reordered: u64,
+    /// TTL of the latest reply and the most common one
+    pub ttl: Option<u8>,
+    pub ttl_typical: Option<u8>,
+    /// "Port closed" replies out of `recv`
+    pub recv_closed: u64,
+    /// Mean RTT of "port closed" replies. The other RTT values are from actual
+    /// replies, or from "port closed" replies if there are no actual ones.
+    pub closed_mean: Option<f64>,
     pub min: Option<u32>,
     pub max: Option<u32>,
     pub mean: Option<f64>,
     pub last: Option<u32>,
     pub stdev: Option<f64>,
+    /// RFC 3550 interarrival jitter (µs)
+    pub jitter: Option<f64>,
+    /// Estimated call quality from mean RTT, jitter and recent loss
+    pub voip: Option<VoipScore>,
+    /// Totals of the selected time window, which replace `sent`, `recv` and `mean`
+    pub window: Option<WindowStats>,
+    pub outages: OutageStats,
+    /// Requested RTT percentiles and their values
+    pub percentiles: Vec<(f64, u32)>,
     pub error: Option<String>,
+    /// Failed probes by cause
+    pub errors: ErrorCounts,
     /// History of recent sent/received packets
     pub hist: HistorySnapshot,
     /// Timestamp of this snapshot.
     pub when: Instant,
     /// The latest sequence number from master data AT THE TIME OF THIS SNAPSHOT
-    pub latest_seq: u16,
+    pub latest_seq: u64,
     /// The instant when the latest packet (latest_seq) was sent
     pub latest_sent: Instant,
     timeout: Duration,
@@ -626,31 +1105,73 @@ impl StatsSnapshot {
     /// Extract a [StatsSnapshot] from [PingTargetInner]
     ///
     /// - `timeout` is the overall ping timeout duration.
-    pub fn new_from(data: &PingTargetInner, timeout: Duration) -> Self {
+    /// - `pcts` are the RTT percentiles to include (see [LatencyWindow::percentiles]).
+    /// - `codec` is the codec profile for the call quality estimate (see [VoipScore]).
+    /// - `window` selects the time window for sent/received packets and mean RTT.
+    pub fn new_from(
+        data: &PingTargetInner,
+        timeout: Duration,
+        pcts: &[f64],
+        codec: Codec,
+        window: StatWindow,
+    ) -> Self {
         let now: Instant = Instant::now();
-        let (mean, min, max) = match data.rtts.mean_min_max() {
+        let rtts: &LatencyWindow = data.rtt_window();
+        let (mut mean, min, max) = match rtts.mean_min_max() {
             Ok((mean, mi, ma)) => (Some(mean), Some(mi), Some(ma)),
             Err(_) => (None, None, None),
         };
+        let win: Option<WindowStats> = data.windows.stats(window, now);
+        let (sent, recv, late) = match win {
+            Some(w) => {
+                mean = w.mean();
+                (w.sent, w.recv, w.late)
+            }
+            None => (data.sent, data.recv, data.late),
+        };
         Self {
             when: now,
-            sent: data.sent,
-            recv: data.recv,
+            sent,
+            recv,
+            late,
+            dups: data.dups,
+            reordered: data.reordered,
+            ttl: data.ttl.current(),
+            ttl_typical: data.ttl.typical(),
+            recv_closed: data.recv_closed,
+            closed_mean: data.closed_rtts.mean().ok(),
             mean,
             min,
             max,
-            last: match data.rtts.last() {
+            last: match rtts.last() {
                 Ok(v) => Some(v),
                 Err(_) => None,
             },
-            stdev: match data.rtts.stdev() {
+            stdev: match rtts.stdev() {
                 Ok(v) => Some(v),
                 Err(_) => None,
             },
+            jitter: data.jitter,
+            window: win,
+            outages: data.outages.stats(SystemTime::now()),
+            voip: mean.map(|m: f64| {
+                let jitter: f64 = data.jitter.unwrap_or(0.0);
+                VoipScore::new(
+                    codec,
+                    m / MICRO_TO_MILLI,
+                    jitter / MICRO_TO_MILLI,
+                    data.recent.loss(),
+                )
+            }),
+            percentiles: match rtts.percentiles(pcts) {
+                Ok(v) => pcts.iter().copied().zip(v).collect(),
+                Err(_) => vec![],
+            },
             error: match &data.status {
                 PingStatus::Error(e) => Some(e.to_string()),
                 _ => None,
             },
+            errors: data.errors,
             hist: HistorySnapshot::new_from(&data.recent),
             latest_seq: data.last_seq,
             latest_sent: data.last_sent.unwrap_or(now),
@@ -663,10 +1184,16 @@ impl StatsSnapshot {
         if self.sent == 0 {
             0.0
         } else {
-            (self.sent - self.recv) as f64 / self.sent as f64
+            self.lost() as f64 / self.sent as f64
         }
     }
 
+    /// Number of lost probes, ie. neither answered in time nor late.
+    #[inline]
+    pub fn lost(&self) -> u64 {
+        self.sent.saturating_sub(self.recv + self.late)
+    }
+
     /// Whether the latest sent packet is still considered "in flight" (not yet timed out).
     ///
     /// NOTE: This is based on this snapshot's creation timestamp (`now`), not the current
@@ -681,8 +1208,9 @@ impl StatsSnapshot {
     pub fn loss_str(&self) -> String {
         if self.sent == 0 {
             "-".to_string()
-        } else if ((self.sent - self.recv) == 1) && self.is_latest_inflight() {
-            // catch the common case of one receive missing (still in transit)
+        } else if self.lost() == 1 && self.window.is_none() && self.is_latest_inflight() {
+            // catch the common case of one receive missing (still in transit).
+            // Time windows only count completed probes, so nothing to catch there.
             "0.0%".to_string()
         } else {
             format!("{:.1}%", 1e2 * self.loss())
@@ -725,6 +1253,52 @@ impl StatsSnapshot {
         }
     }
 
+    /// RTT percentile `p` as formatted string (as milliseconds).
+    #[inline]
+    pub fn percentile_str(&self, p: f64) -> String {
+        match self.percentiles.iter().find(|(pct, _)| *pct == p) {
+            Some((_, v)) => format!("{:.2}", *v as f64 / MICRO_TO_MILLI),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Jitter as formatted string (as milliseconds).
+    #[inline]
+    pub fn jitter_str(&self) -> String {
+        match self.jitter {
+            Some(v) => format!("{:.2}", v / MICRO_TO_MILLI),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Reply TTL as formatted string, with the typical one if it differs, eg. `55 (57)`.
+    #[inline]
+    pub fn ttl_str(&self) -> String {
+        match (self.ttl, self.ttl_typical) {
+            (Some(cur), Some(typ)) if cur != typ => format!("{cur} ({typ})"),
+            (Some(cur), _) => cur.to_string(),
+            _ => "-".to_string(),
+        }
+    }
+
+    /// Estimated MOS as formatted string.
+    #[inline]
+    pub fn mos_str(&self) -> String {
+        match self.voip {
+            Some(v) => format!("{:.2}", v.mos),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Estimated R-factor as formatted string.
+    #[inline]
+    pub fn r_factor_str(&self) -> String {
+        match self.voip {
+            Some(v) => format!("{:.0}", v.r),
+            None => "-".to_string(),
+        }
+    }
+
     /// Standard deviation as formatted string (as milliseconds).
     #[inline]
     pub fn stdev_str(&self) -> String {
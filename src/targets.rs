@@ -0,0 +1,324 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{
+    args::{INTERVAL_MAX, INTERVAL_MIN, TIMEOUT_MAX, TIMEOUT_MIN},
+    ip_addresses::{AddrFamily, is_hostname, parse_ip_or_range, resolve_host},
+    probe::Proto,
+    rules::RuleOverrides,
+    sockopts::SockOpts,
+    utils::parse_float_into_duration,
+};
+use std::{fs, net::IpAddr, path::Path, time::Duration};
+
+/// Per-target overrides of global options. `None` means "use the global value".
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct TargetOpts {
+    pub interval: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub proto: Option<Proto>,
+    /// Status classification rules
+    pub rules: RuleOverrides,
+    /// Outgoing TTL, DSCP marking and DF flag
+    pub sock: SockOpts,
+}
+
+/// Specification of a single ping target, ie. what to ping and how to present it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TargetSpec {
+    /// Address to ping. For hostname targets this is the address resolved at startup.
+    pub addr: IpAddr,
+    /// Hostname, if the target was given by name. Displayed instead of the address.
+    pub host: Option<String>,
+    /// Free-form label shown next to the address
+    pub label: Option<String>,
+    /// Group name for grouping targets
+    pub group: Option<String>,
+    pub opts: TargetOpts,
+}
+
+impl TargetSpec {
+    /// Create a [TargetSpec] for a plain IP address.
+    pub fn new(addr: IpAddr) -> Self {
+        Self {
+            addr,
+            host: None,
+            label: None,
+            group: None,
+            opts: TargetOpts::default(),
+        }
+    }
+
+    /// Identity key for de-duplication: hostname targets by name, others by address.
+    /// An explicit probe protocol and socket options are part of the key, so a host
+    /// can be monitored with several protocols or DSCP markings at once.
+    pub fn key(&self) -> String {
+        target_key(self.host.as_deref(), self.addr, &self.opts)
+    }
+}
+
+/// See [TargetSpec::key].
+pub(crate) fn target_key(host: Option<&str>, addr: IpAddr, opts: &TargetOpts) -> String {
+    let mut key: String = match host {
+        Some(h) => h.to_lowercase(),
+        None => addr.to_string(),
+    };
+    if let Some(p) = opts.proto {
+        key = format!("{key} {p}");
+    }
+    if !opts.sock.is_empty() {
+        key = format!("{key} {}", opts.sock);
+    }
+    key
+}
+
+/// Expand a single target argument into one or more [TargetSpec]s.
+///
+/// Anything [parse_ip_or_range] understands is expanded into individual addresses.
+/// Failing that, hostnames are resolved and the first address (honoring the
+/// family preference `pref`) is used.
+///
+/// A `:port` suffix (`[addr]:port` for IPv6) selects TCP probing of that port.
+pub fn expand_target(arg: &str, pref: AddrFamily) -> Result<Vec<TargetSpec>, String> {
+    let err: String = match parse_ip_or_range(arg) {
+        Ok(ips) => return Ok(ips.into_iter().map(TargetSpec::new).collect()),
+        Err(e) => e,
+    };
+    if let Some((host, port)) = split_port(arg) {
+        let mut specs: Vec<TargetSpec> = expand_target(host, pref)?;
+        for spec in &mut specs {
+            spec.opts.proto = Some(Proto::Tcp(port));
+        }
+        return Ok(specs);
+    }
+    if !is_hostname(arg) {
+        return Err(err);
+    }
+
+    let addrs: Vec<IpAddr> = resolve_host(arg, pref)?;
+    let mut spec: TargetSpec = TargetSpec::new(addrs[0]);
+    spec.host = Some(arg.trim_end_matches('.').to_string());
+    Ok(vec![spec])
+}
+
+/// Split `host:port` or `[addr]:port` into its parts. Bare IPv6 addresses
+/// are never split, since the last group could be mistaken for a port.
+fn split_port(arg: &str) -> Option<(&str, u16)> {
+    let (host, port) = arg.rsplit_once(':')?;
+    let port: u16 = port.parse().ok().filter(|p: &u16| *p > 0)?;
+    match host
+        .strip_prefix('[')
+        .and_then(|h: &str| h.strip_suffix(']'))
+    {
+        Some(v6) => Some((v6, port)),
+        None if !host.contains(':') => Some((host, port)),
+        None => None,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Parse a targets file. See [parse_target_line] for the line format.
+/// Errors are reported with the offending line number.
+pub fn parse_targets_file(path: &Path, pref: AddrFamily) -> Result<Vec<TargetSpec>, String> {
+    let content: String =
+        fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+
+    let mut specs: Vec<TargetSpec> = Vec::new();
+    for (num, line) in content.lines().enumerate() {
+        match parse_target_line(line, pref) {
+            Ok(mut s) => specs.append(&mut s),
+            Err(e) => return Err(format!("{}:{}: {e}", path.display(), num + 1)),
+        }
+    }
+    Ok(specs)
+}
+
+/// Parse a single line of a targets file. Format:
+///
+/// ```text
+/// # comment
+/// <target> [label=<text>] [group=<name>] [interval=<secs>] [timeout=<secs>] [tcp|udp=<port>]  # comment
+/// ```
+///
+/// Status rules can be overridden too, eg. `laggy=20:1.5 dwell=5`, see [RuleOverrides::set],
+/// and so can the outgoing IP options, eg. `dscp=ef ttl=64 df=yes`, see [SockOpts::set].
+///
+/// Target is anything [expand_target] accepts. Values containing spaces
+/// can be double-quoted, eg. `label="core switch"`. Empty and comment-only
+/// lines yield no targets.
+pub fn parse_target_line(line: &str, pref: AddrFamily) -> Result<Vec<TargetSpec>, String> {
+    let tokens: Vec<String> = tokenize(line)?;
+    let Some((target, options)) = tokens.split_first() else {
+        return Ok(vec![]);
+    };
+
+    let (mut label, mut group) = (None, None);
+    let mut opts: TargetOpts = TargetOpts::default();
+    for opt in options {
+        let (key, val) = opt
+            .split_once('=')
+            .ok_or_else(|| format!("Expected key=value, got '{opt}'"))?;
+        match key {
+            "label" => label = Some(val.to_string()),
+            "group" => group = Some(val.to_string()),
+            "interval" => {
+                let d: Duration = parse_float_into_duration(val)?;
+                opts.interval = Some(d.clamp(INTERVAL_MIN, INTERVAL_MAX));
+            }
+            "timeout" => {
+                let d: Duration = parse_float_into_duration(val)?;
+                opts.timeout = Some(d.clamp(TIMEOUT_MIN, TIMEOUT_MAX));
+            }
+            "tcp" | "udp" => {
+                let port: u16 = match val.parse::<u16>() {
+                    Ok(port) if port > 0 => port,
+                    _ => return Err(format!("Invalid port '{val}'")),
+                };
+                opts.proto = Some(match key {
+                    "tcp" => Proto::Tcp(port),
+                    _ => Proto::Udp(port),
+                });
+            }
+            _ if opts.rules.set(key, val)? => {}
+            _ if opts.sock.set(key, val)? => {}
+            _ => return Err(format!("Unknown option '{key}'")),
+        }
+    }
+
+    let mut specs: Vec<TargetSpec> = expand_target(target, pref)?;
+    for spec in &mut specs {
+        spec.label = label.clone();
+        spec.group = group.clone();
+        // a port given with the target itself applies unless overridden
+        spec.opts = TargetOpts {
+            proto: opts.proto.or(spec.opts.proto),
+            ..opts.clone()
+        };
+        // the IP options are set on the ICMP sockets only
+        if let Some(proto) = spec.opts.proto
+            && !opts.sock.is_empty()
+        {
+            return Err(format!(
+                "IP options '{}' apply to ICMP targets only, not {proto}",
+                opts.sock
+            ));
+        }
+    }
+    Ok(specs)
+}
+
+/// Split a line into whitespace separated tokens. Double quotes group words
+/// together (quotes are removed) and `#` outside quotes starts a comment.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut cur: String = String::new();
+    let mut quoted: bool = false;
+    let mut in_token: bool = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_token = true;
+            }
+            '#' if !quoted => break,
+            c if c.is_whitespace() && !quoted => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut cur));
+                    in_token = false;
+                }
+            }
+            c => {
+                cur.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("Unterminated quote".to_string());
+    }
+    if in_token {
+        tokens.push(cur);
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens: Vec<String> =
+            tokenize(r#"10.0.0.1 label="core switch" group=lab # trailing"#).unwrap();
+        assert_eq!(tokens, ["10.0.0.1", "label=core switch", "group=lab"]);
+        assert!(tokenize("# just a comment").unwrap().is_empty());
+        assert!(tokenize("   ").unwrap().is_empty());
+        assert!(tokenize(r#"10.0.0.1 label="oops"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let specs: Vec<TargetSpec> = parse_target_line(
+            "10.0.0.1-3 label=rack1 group=dc1 interval=0.5 laggy=20:1.5",
+            AddrFamily::Any,
+        )
+        .unwrap();
+        assert_eq!(specs.len(), 3);
+        assert!(specs.iter().all(|s| s.label.as_deref() == Some("rack1")));
+        assert!(specs.iter().all(|s| s.group.as_deref() == Some("dc1")));
+        assert_eq!(specs[0].opts.interval, Some(Duration::from_millis(500)));
+        assert_eq!(specs[0].opts.timeout, None);
+        assert_eq!(specs[1].opts.rules.laggy, Some((20, 1.5)));
+        assert_eq!(specs[2].addr, "10.0.0.3".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_parse_line_errors() {
+        assert!(parse_target_line("", AddrFamily::Any).unwrap().is_empty());
+        assert!(parse_target_line("10.0.0.1 rack1", AddrFamily::Any).is_err());
+        assert!(parse_target_line("10.0.0.1 color=red", AddrFamily::Any).is_err());
+        assert!(parse_target_line("10.0.0.1 timeout=x", AddrFamily::Any).is_err());
+        assert!(parse_target_line("10.0.0.1 tcp=0", AddrFamily::Any).is_err());
+        assert!(parse_target_line("10.0.0.1 lossy=5", AddrFamily::Any).is_err());
+    }
+
+    #[test]
+    fn test_target_ports() {
+        assert_eq!(split_port("example.com:443"), Some(("example.com", 443)));
+        assert_eq!(split_port("[2001:db8::1]:22"), Some(("2001:db8::1", 22)));
+        assert_eq!(split_port("2001:db8::1"), None);
+        assert_eq!(split_port("10.0.0.1:0"), None);
+
+        let specs: Vec<TargetSpec> = expand_target("10.0.0.1-2:443", AddrFamily::Any).unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[1].opts.proto, Some(Proto::Tcp(443)));
+        assert_eq!(specs[1].key(), "10.0.0.2 tcp/443");
+
+        let specs: Vec<TargetSpec> =
+            parse_target_line("[::1]:80 label=web", AddrFamily::Any).unwrap();
+        assert_eq!(specs[0].opts.proto, Some(Proto::Tcp(80)));
+        let specs: Vec<TargetSpec> =
+            parse_target_line("[::1]:80 tcp=8080", AddrFamily::Any).unwrap();
+        assert_eq!(specs[0].opts.proto, Some(Proto::Tcp(8080)));
+        let specs: Vec<TargetSpec> = parse_target_line("::1 udp=53", AddrFamily::Any).unwrap();
+        assert_eq!(specs[0].opts.proto, Some(Proto::Udp(53)));
+    }
+
+    #[test]
+    fn test_target_markings() {
+        let ef: Vec<TargetSpec> =
+            parse_target_line("10.0.0.1 dscp=ef df=yes", AddrFamily::Any).unwrap();
+        let be: Vec<TargetSpec> = parse_target_line("10.0.0.1 dscp=be", AddrFamily::Any).unwrap();
+        assert_eq!(ef[0].opts.sock.df, Some(true));
+        assert_eq!(ef[0].key(), "10.0.0.1 ef df");
+        assert_eq!(be[0].key(), "10.0.0.1 be");
+        assert_ne!(ef[0].key(), TargetSpec::new(ef[0].addr).key());
+        assert!(parse_target_line("10.0.0.1 dscp=xx", AddrFamily::Any).is_err());
+        // not for TCP or UDP probes
+        assert!(parse_target_line("10.0.0.1 tcp=80 dscp=ef", AddrFamily::Any).is_err());
+        assert!(parse_target_line("10.0.0.1:53 ttl=5", AddrFamily::Any).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Statistics over wall-clock windows (1, 5 and 15 minutes), independent of the
+//! probe interval. Probe outcomes are collected into buckets of 1/60th of each
+//! window (1, 5 and 15 seconds) and the window totals are maintained incrementally
+//! as buckets expire. The windows thus move in steps of their bucket length.
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// Lengths (seconds) of the windows
+const WINDOW_SECS: [u64; 3] = [60, 300, 900];
+/// Number of buckets per window
+const BUCKETS: u64 = 60;
+
+/// Statistics window selectable in the TUI.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StatWindow {
+    /// All samples kept (`--histsize`)
+    #[default]
+    All,
+    M1,
+    M5,
+    M15,
+}
+
+impl StatWindow {
+    /// Next window in the cycle `all -> 1m -> 5m -> 15m -> all`.
+    pub fn next(&self) -> Self {
+        match self {
+            StatWindow::All => StatWindow::M1,
+            StatWindow::M1 => StatWindow::M5,
+            StatWindow::M5 => StatWindow::M15,
+            StatWindow::M15 => StatWindow::All,
+        }
+    }
+
+    /// Index into [TimeWindows] totals, `None` for [StatWindow::All].
+    fn index(&self) -> Option<usize> {
+        match self {
+            StatWindow::All => None,
+            StatWindow::M1 => Some(0),
+            StatWindow::M5 => Some(1),
+            StatWindow::M15 => Some(2),
+        }
+    }
+}
+
+impl Display for StatWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatWindow::All => write!(f, "all"),
+            StatWindow::M1 => write!(f, "1m"),
+            StatWindow::M5 => write!(f, "5m"),
+            StatWindow::M15 => write!(f, "15m"),
+        }
+    }
+}
+
+/// Probe outcome totals of a bucket or a window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct WindowStats {
+    /// Completed probes (replied or timed out)
+    pub sent: u64,
+    pub recv: u64,
+    /// Timed out probes answered later, within the grace period (not in `recv`)
+    pub late: u64,
+    /// Sum of reply RTTs in microseconds
+    pub rtt_sum: u64,
+}
+
+impl WindowStats {
+    /// Packet loss (late replies excluded) as a float `[0.0 .. 1.0]`, `None` if
+    /// there are no probes.
+    pub fn loss(&self) -> Option<f64> {
+        match self.sent {
+            0 => None,
+            n => Some(n.saturating_sub(self.recv + self.late) as f64 / n as f64),
+        }
+    }
+
+    /// Mean RTT in microseconds, `None` if there are no replies.
+    pub fn mean(&self) -> Option<f64> {
+        match self.recv {
+            0 => None,
+            n => Some(self.rtt_sum as f64 / n as f64),
+        }
+    }
+
+    fn add(&mut self, other: &WindowStats) {
+        self.sent += other.sent;
+        self.recv += other.recv;
+        self.late += other.late;
+        self.rtt_sum += other.rtt_sum;
+    }
+
+    fn sub(&mut self, other: &WindowStats) {
+        self.sent -= other.sent;
+        self.recv -= other.recv;
+        self.late -= other.late;
+        self.rtt_sum -= other.rtt_sum;
+    }
+}
+
+/// Probe outcomes of one bucket length.
+#[derive(Debug, Default, Clone, Copy)]
+struct Bucket {
+    /// Slot (bucket length periods since start) this bucket currently holds
+    slot: u64,
+    stats: WindowStats,
+}
+
+/// Bucket ring and running total of a single window.
+#[derive(Debug, Clone)]
+struct Ring {
+    /// Bucket length in seconds
+    width: u64,
+    buckets: Vec<Bucket>,
+    total: WindowStats,
+}
+
+impl Ring {
+    fn new(len: u64) -> Self {
+        Self {
+            width: len / BUCKETS,
+            buckets: vec![],
+            total: WindowStats::default(),
+        }
+    }
+
+    /// Contents of the bucket leaving the window when it reaches `slot`.
+    #[inline]
+    fn expiring(&self, slot: u64) -> Option<WindowStats> {
+        let old: u64 = slot.checked_sub(BUCKETS)?;
+        let bucket: &Bucket = self.buckets.get((old % BUCKETS) as usize)?;
+        (bucket.slot == old).then_some(bucket.stats)
+    }
+
+    /// Move the window end from second `head` to second `now`.
+    fn advance(&mut self, head: u64, now: u64) {
+        let (from, to) = (head / self.width, now / self.width);
+        if to - from >= BUCKETS {
+            // everything has expired
+            self.buckets.fill(Bucket::default());
+            self.total = WindowStats::default();
+            return;
+        }
+        for slot in from + 1..=to {
+            if let Some(old) = self.expiring(slot) {
+                self.total.sub(&old);
+            }
+        }
+    }
+
+    /// Add `stats` to the bucket of second `sec`, if still within the window ending
+    /// at second `head`.
+    fn add(&mut self, sec: u64, stats: &WindowStats, head: u64) {
+        let slot: u64 = sec / self.width;
+        if slot + BUCKETS <= head / self.width {
+            return; // outside the window already
+        }
+        // buckets are allocated on first use to keep idle targets cheap
+        if self.buckets.is_empty() {
+            self.buckets = vec![Bucket::default(); BUCKETS as usize];
+        }
+        let bucket: &mut Bucket = &mut self.buckets[(slot % BUCKETS) as usize];
+        if bucket.slot != slot {
+            *bucket = Bucket {
+                slot,
+                ..Default::default()
+            };
+        }
+        bucket.stats.add(stats);
+        self.total.add(stats);
+    }
+
+    /// Total as of second `now`, given the totals are up to date with second `head`.
+    fn total_at(&self, head: u64, now: u64) -> WindowStats {
+        let (from, to) = (head / self.width, now / self.width);
+        if to <= from {
+            return self.total;
+        }
+        if to - from >= BUCKETS {
+            return WindowStats::default();
+        }
+        // catch up without modifying: drop what has expired since the last update
+        let mut total: WindowStats = self.total;
+        for slot in from + 1..=to {
+            if let Some(old) = self.expiring(slot) {
+                total.sub(&old);
+            }
+        }
+        total
+    }
+}
+
+/// Bucketed probe outcomes of the last 15 minutes and running totals of each window.
+///
+/// Probes are accounted to the second they were sent in, but only once their
+/// outcome is known, so probes still in flight never count as lost.
+#[derive(Debug, Clone)]
+pub(crate) struct TimeWindows {
+    start: Instant,
+    rings: [Ring; 3],
+    /// Latest second the totals are up to date with
+    head: u64,
+}
+
+impl Default for TimeWindows {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl TimeWindows {
+    /// Create new empty [TimeWindows] with the clock starting at `start`.
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            rings: WINDOW_SECS.map(Ring::new),
+            head: 0,
+        }
+    }
+
+    /// Forget everything, restarting the clock at `start`.
+    pub fn clear(&mut self, start: Instant) {
+        *self = Self::new(start);
+    }
+
+    #[inline]
+    fn sec_of(&self, t: Instant) -> u64 {
+        t.saturating_duration_since(self.start).as_secs()
+    }
+
+    /// Record the outcome of a probe sent at `sent`: the RTT of its reply, or `None`
+    /// if it was lost. `now` is the current time.
+    pub fn record(&mut self, sent: Instant, rtt: Option<Duration>, now: Instant) {
+        let stats: WindowStats = WindowStats {
+            sent: 1,
+            recv: rtt.is_some() as u64,
+            late: 0,
+            rtt_sum: rtt.map_or(0, |d: Duration| d.as_micros() as u64),
+        };
+        self.add(sent, &stats, now);
+    }
+
+    /// Record a late reply to a probe sent at `sent`, which was already recorded
+    /// as lost.
+    pub fn record_late(&mut self, sent: Instant, now: Instant) {
+        let stats: WindowStats = WindowStats {
+            late: 1,
+            ..Default::default()
+        };
+        self.add(sent, &stats, now);
+    }
+
+    /// Add `stats` to the buckets of second `sent` in the windows it falls within.
+    fn add(&mut self, sent: Instant, stats: &WindowStats, now: Instant) {
+        let now: u64 = self.sec_of(now);
+        if now > self.head {
+            for ring in &mut self.rings {
+                ring.advance(self.head, now);
+            }
+            self.head = now;
+        }
+        let sec: u64 = self.sec_of(sent);
+        for ring in &mut self.rings {
+            ring.add(sec, stats, self.head);
+        }
+    }
+
+    /// Totals of the selected window as of `now`, `None` for [StatWindow::All].
+    pub fn stats(&self, window: StatWindow, now: Instant) -> Option<WindowStats> {
+        let idx: usize = window.index()?;
+        Some(self.rings[idx].total_at(self.head, self.sec_of(now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_windows() {
+        let start: Instant = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let ms = |v: u64| Some(Duration::from_millis(v));
+        let mut tw: TimeWindows = TimeWindows::new(start);
+
+        // one probe per second for 10 minutes: 10ms RTT, except the first 2
+        // minutes are at 30ms and every 10th probe in the 5th minute is lost
+        for sec in 0..600 {
+            let rtt: Option<Duration> = match sec {
+                0..120 => ms(30),
+                240..300 if sec % 10 == 0 => None,
+                _ => ms(10),
+            };
+            tw.record(at(sec), rtt, at(sec) + Duration::from_millis(10));
+        }
+        let now: Instant = at(599);
+        let m1: WindowStats = tw.stats(StatWindow::M1, now).unwrap();
+        assert_eq!((m1.sent, m1.recv, m1.loss()), (60, 60, Some(0.0)));
+        assert_eq!(m1.mean(), Some(10_000.0));
+
+        let m5: WindowStats = tw.stats(StatWindow::M5, now).unwrap();
+        assert_eq!((m5.sent, m5.recv), (300, 300));
+
+        let m15: WindowStats = tw.stats(StatWindow::M15, now).unwrap();
+        assert_eq!((m15.sent, m15.recv), (600, 594));
+        assert_eq!(m15.mean(), Some((120.0 * 30e3 + 474.0 * 10e3) / 594.0));
+        assert_eq!(tw.stats(StatWindow::All, now), None);
+
+        // a late reply goes to the second it was sent in
+        tw.record(at(590), None, at(600));
+        assert_eq!(tw.stats(StatWindow::M1, at(600)).unwrap().sent, 60);
+        // ... and an even later one within the grace period is not loss anymore
+        tw.record_late(at(590), at(601));
+        let m1: WindowStats = tw.stats(StatWindow::M1, at(601)).unwrap();
+        assert_eq!((m1.late, m1.loss()), (1, Some(0.0)));
+
+        // the 5 minute window moves in 5 second steps: over 600..=604 it holds the
+        // probes sent in 305..=599 (and the extra one), at 605 another 5 expire at once
+        assert_eq!(tw.stats(StatWindow::M5, at(604)).unwrap().sent, 296);
+        assert_eq!(tw.stats(StatWindow::M5, at(605)).unwrap().sent, 291);
+
+        // reading ahead in time expires old buckets without recording anything
+        let m1: WindowStats = tw.stats(StatWindow::M1, at(630)).unwrap();
+        assert_eq!((m1.sent, m1.recv), (30, 29));
+        assert_eq!(tw.stats(StatWindow::M5, at(2000)).unwrap().sent, 0);
+
+        // idle for longer than all windows
+        tw.record(at(3000), ms(5), at(3000));
+        let m15: WindowStats = tw.stats(StatWindow::M15, at(3000)).unwrap();
+        assert_eq!((m15.sent, m15.recv, m15.mean()), (1, 1, Some(5000.0)));
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Reply TTL (IPv4) / hop limit (IPv6) tracking. The TTL of the replies from a
+//! host only changes when the number of hops on the return path does, so a change
+//! is the cheapest hint of a route change (eg. asymmetric routing after a failover).
+
+use crate::utils::local_time_str;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+    time::SystemTime,
+};
+
+/// Maximum number of TTL changes kept in the log (the counter covers all of them)
+const TTL_LOG_MAX: usize = 50;
+/// Number of replies after a TTL change during which it counts as recent
+pub(crate) const ROUTE_CHANGE_HOLD: u64 = 10;
+
+/// A change of the reply TTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TtlChange {
+    /// Send time of the first probe answered with the new TTL
+    pub at: SystemTime,
+    pub from: u8,
+    pub to: u8,
+}
+
+impl Display for TtlChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} TTL {} -> {}",
+            local_time_str(self.at),
+            self.from,
+            self.to
+        )
+    }
+}
+
+/// Per-target reply TTL tracker.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TtlTracker {
+    current: Option<u8>,
+    /// Number of replies seen with each TTL
+    counts: BTreeMap<u8, u64>,
+    /// Replies since the latest change
+    since_change: u64,
+    changes: u64,
+    /// Recent changes, oldest first
+    log: VecDeque<TtlChange>,
+}
+
+impl TtlTracker {
+    /// Record the TTL of a reply to a probe sent at `at`. Returns the change if the
+    /// TTL differs from the previous reply.
+    pub fn record(&mut self, ttl: u8, at: SystemTime) -> Option<TtlChange> {
+        *self.counts.entry(ttl).or_default() += 1;
+        let prev: Option<u8> = self.current.replace(ttl);
+        match prev {
+            Some(from) if from != ttl => {
+                let change: TtlChange = TtlChange { at, from, to: ttl };
+                if self.log.len() == TTL_LOG_MAX {
+                    self.log.pop_front();
+                }
+                self.log.push_back(change);
+                self.changes += 1;
+                self.since_change = 0;
+                Some(change)
+            }
+            _ => {
+                self.since_change += 1;
+                None
+            }
+        }
+    }
+
+    /// TTL of the latest reply.
+    #[inline]
+    pub fn current(&self) -> Option<u8> {
+        self.current
+    }
+
+    /// Most common TTL so far (the higher one on a tie).
+    pub fn typical(&self) -> Option<u8> {
+        self.counts
+            .iter()
+            .max_by_key(|(_, n)| **n)
+            .map(|(ttl, _)| *ttl)
+    }
+
+    /// Total number of TTL changes.
+    #[inline]
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    /// Whether the TTL changed within the last [ROUTE_CHANGE_HOLD] replies.
+    pub fn recently_changed(&self) -> bool {
+        self.changes > 0 && self.since_change < ROUTE_CHANGE_HOLD
+    }
+
+    /// Logged changes, oldest first.
+    pub fn log(&self) -> &VecDeque<TtlChange> {
+        &self.log
+    }
+
+    /// One-line summary, eg. `55 (typical 57), 2 changes`.
+    pub fn summary(&self) -> String {
+        match (self.current, self.typical()) {
+            (Some(cur), Some(typ)) => {
+                format!("{cur} (typical {typ}), {} changes", self.changes)
+            }
+            _ => "-".to_string(),
+        }
+    }
+
+    /// Forget everything.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_tracker() {
+        let t0: SystemTime = SystemTime::UNIX_EPOCH;
+        let mut tr: TtlTracker = TtlTracker::default();
+        assert_eq!(
+            (tr.current(), tr.typical(), tr.summary()),
+            (None, None, "-".into())
+        );
+
+        for _ in 0..5 {
+            assert_eq!(tr.record(57, t0), None);
+        }
+        assert!(!tr.recently_changed());
+        let change: Option<TtlChange> = tr.record(55, t0);
+        assert_eq!(
+            change,
+            Some(TtlChange {
+                at: t0,
+                from: 57,
+                to: 55
+            })
+        );
+        assert!(tr.recently_changed());
+        assert_eq!(tr.summary(), "55 (typical 57), 1 changes");
+
+        for _ in 0..ROUTE_CHANGE_HOLD {
+            assert!(tr.recently_changed());
+            tr.record(55, t0);
+        }
+        assert!(!tr.recently_changed());
+        assert_eq!(tr.typical(), Some(55));
+        assert_eq!(tr.log().len(), 1);
+
+        tr.clear();
+        assert_eq!(tr.changes(), 0);
+    }
+}
@@ -2,7 +2,7 @@
 // Licensed under the MIT License or the Apache License, Version 2.0.
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use crate::structs::AppState;
+use crate::structs::{AppState, PingTarget, TargetCmd};
 use crossterm::{
     cursor::{Hide, Show},
     event::{self, Event, KeyCode, KeyModifiers},
@@ -232,6 +232,11 @@ impl TableRow {
         idx
     }
 
+    /// Insert an item at `idx`, shifting the following items right.
+    pub fn insert_item<I: AsRef<str>>(&mut self, idx: usize, item: I) {
+        self.items.insert(idx.min(self.len()), TableItem::new(item));
+    }
+
     /// Set the style for a specific item in this row.
     pub fn set_style(&mut self, idx: usize, style: Style) {
         if let Some(item) = self.items.get_mut(idx) {
@@ -293,6 +298,34 @@ impl<'a> IntoIterator for &'a TableRow {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Modal input prompt shown on the status line. While active, it receives all key input.
+#[derive(Debug)]
+pub(crate) enum Prompt {
+    /// Text input for new target(s), and the error of the previous attempt
+    AddTarget(String, Option<String>),
+    /// Confirmation for removing a target
+    ConfirmRemove(Arc<PingTarget>),
+}
+
+impl fmt::Display for Prompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Prompt::AddTarget(txt, None) => {
+                write!(f, "Add target (IP, range, CIDR or hostname): {txt}_")
+            }
+            Prompt::AddTarget(txt, Some(err)) => {
+                write!(
+                    f,
+                    "Add target (IP, range, CIDR or hostname): {txt}_  [{err}]"
+                )
+            }
+            Prompt::ConfirmRemove(tgt) => write!(f, "Remove {}? [y/N]", tgt.name()),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 /// RAII guard object for TUI console using [ratatui] and [crossterm].
 /// - sets up a panic handler to restore normal terminal on panic
 /// - initializes a full-screen TUI on creation (the RAII part)
@@ -354,16 +387,26 @@ pub(crate) fn panic_handler(info: &panic::PanicHookInfo) {
 ///
 /// ### Returns
 /// - `Ok(bool)` indicating whether a handled key event occurred
-pub(crate) fn key_event_poll(wait_ms: u64, q: &Arc<AtomicBool>, s: &AppState) -> Result<bool> {
+pub(crate) fn key_event_poll(wait_ms: u64, q: &Arc<AtomicBool>, s: &mut AppState) -> Result<bool> {
     if event::poll(Duration::from_millis(wait_ms))? {
         if let Event::Key(e) = event::read()? {
+            // terminal in raw mode -> ctrl-c has to be processed manually, prompt or not
+            if (e.code, e.modifiers) == (KeyCode::Char('c'), KeyModifiers::CONTROL) {
+                q.store(true, Relaxed);
+                return Ok(true);
+            }
+
+            // An active prompt takes all input. Don't clear the event queue
+            // afterwards either, since that would drop fast typed characters.
+            if s.prompt.is_some() {
+                prompt_key_event(e.code, e.modifiers, s);
+                return Ok(true);
+            }
+
             match (e.code, e.modifiers) {
                 // Quit the application
                 (KeyCode::Char('q'), _) => q.store(true, Relaxed),
 
-                // terminal in raw mode -> ctrl-c has to be processed manually
-                (KeyCode::Char('c'), KeyModifiers::CONTROL) => q.store(true, Relaxed),
-
                 // Table navigation: up/down
                 (KeyCode::Up, _) => s.layout.write().tablestate.select_previous(),
                 (KeyCode::Down, _) => s.layout.write().tablestate.select_next(),
@@ -409,6 +452,22 @@ pub(crate) fn key_event_poll(wait_ms: u64, q: &Arc<AtomicBool>, s: &AppState) ->
                     }
                 }
 
+                // Cycle the statistics time window
+                (KeyCode::Char('w'), _) => s.cycle_window(),
+
+                // Add new target(s)
+                (KeyCode::Char('a'), _) => s.prompt = Some(Prompt::AddTarget(String::new(), None)),
+
+                // Remove the selected target (after confirmation)
+                (KeyCode::Char('d'), _) => {
+                    let sel_idx = s.layout.read().tablestate.selected();
+                    let tgt: Option<Arc<PingTarget>> =
+                        sel_idx.and_then(|i| s.targets.get(i)).cloned();
+                    if let Some(tgt) = tgt {
+                        s.prompt = Some(Prompt::ConfirmRemove(tgt));
+                    }
+                }
+
                 // Don't signal an unhandled key event
                 _ => return Ok(false),
             }
@@ -426,3 +485,38 @@ pub(crate) fn key_event_poll(wait_ms: u64, q: &Arc<AtomicBool>, s: &AppState) ->
         Ok(false)
     }
 }
+
+/// Handle a key press while a [Prompt] is active. Completed prompts queue
+/// a [TargetCmd] for the main loop to carry out.
+fn prompt_key_event(code: KeyCode, mods: KeyModifiers, s: &mut AppState) {
+    let Some(prompt) = s.prompt.take() else {
+        return;
+    };
+    // control and alt combinations are not text
+    let typed: bool = !mods.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+
+    s.prompt = match (prompt, code) {
+        (_, KeyCode::Esc) => None,
+
+        (Prompt::AddTarget(mut txt, _), KeyCode::Char(c)) if typed => {
+            txt.push(c);
+            Some(Prompt::AddTarget(txt, None))
+        }
+        (Prompt::AddTarget(mut txt, _), KeyCode::Backspace) => {
+            txt.pop();
+            Some(Prompt::AddTarget(txt, None))
+        }
+        // hostnames are resolved by the main loop, which reopens the prompt on errors
+        (Prompt::AddTarget(txt, _), KeyCode::Enter) => {
+            s.pending.push(TargetCmd::Add(txt.trim().to_string()));
+            None
+        }
+        (p @ Prompt::AddTarget(..), _) => Some(p),
+
+        (Prompt::ConfirmRemove(tgt), KeyCode::Char('y' | 'Y')) if typed => {
+            s.pending.push(TargetCmd::Remove(tgt));
+            None
+        }
+        (Prompt::ConfirmRemove(_), _) => None,
+    };
+}
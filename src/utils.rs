@@ -3,21 +3,21 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use signal_hook::{
-    consts::signal::{SIGINT, SIGQUIT, SIGTERM},
+    consts::signal::{SIGHUP, SIGINT, SIGQUIT, SIGTERM},
     iterator::{Signals, SignalsInfo},
 };
 use std::{
-    env,
+    env, fs,
     io::{
         Error,
         ErrorKind::{Other, PermissionDenied},
     },
-    path::{MAIN_SEPARATOR, PathBuf},
+    path::{MAIN_SEPARATOR, Path, PathBuf},
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
     },
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 /// Set up handlers for various termination signals.
@@ -50,6 +50,25 @@ pub(crate) fn setup_signal_handler(quit: Arc<AtomicBool>) {
     });
 }
 
+/// Set up a [SIGHUP] handler which raises the `reload` flag (eg. `kill -HUP <pid>`).
+///
+/// NOTE: this replaces the default action of terminating the process, so it should
+/// only be installed when there is something to reload.
+pub(crate) fn setup_reload_handler(reload: Arc<AtomicBool>) {
+    let mut signals: SignalsInfo = Signals::new([SIGHUP]).expect("Error setting up SIGHUP handler");
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            reload.store(true, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Last modification time of a file, if it can be determined.
+pub(crate) fn file_mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
 /// Nicely handle permission errors when creating raw sockets.
 pub(crate) fn nice_permission_error(err: &Error, ip_ver: &str) -> Box<dyn std::error::Error> {
     let msg: String = err.to_string().to_lowercase();
@@ -61,18 +80,30 @@ pub(crate) fn nice_permission_error(err: &Error, ip_ver: &str) -> Box<dyn std::e
             .unwrap_or_else(|| "mping".to_string());
         let bin_path: PathBuf = env::current_exe().unwrap_or_else(|_| PathBuf::from(&name));
 
-        eprintln!("This program requires CAP_NET_RAW to send ICMP packets.");
+        eprintln!("This program requires CAP_NET_RAW to send ICMP packets with raw sockets.");
         eprintln!("Either run \"{name}\" with sudo, or grant the capability to the binary:");
         eprintln!("    sudo setcap cap_net_raw+ep {}", bin_path.display());
-        if ip_ver == "v4" {
-            eprintln!();
-            eprintln!("For IPv4 only you can also allow group IDs system-wide (less secure):");
-            eprintln!("    sudo sysctl -w net.ipv4.ping_group_range=\"<start> <end>\"");
-            eprintln!();
+        eprintln!();
+        eprintln!(
+            "Alternatively allow unprivileged ICMP sockets (IPv4 and IPv6) for your group IDs:"
+        );
+        eprintln!("    sudo sysctl -w net.ipv4.ping_group_range=\"<start> <end>\"");
+        if let Some((start, end)) = ping_group_range() {
+            let gids: Vec<u32> = process_gids();
+            let allowed: bool = gids.iter().any(|g: &u32| (start..=end).contains(g));
+            eprintln!(
+                "Current range is \"{start} {end}\", which {} your group IDs {gids:?}.",
+                if allowed {
+                    "includes"
+                } else {
+                    "does not include"
+                }
+            );
         }
+        eprintln!();
         Box::new(Error::new(
             PermissionDenied,
-            format!("Cannot create raw ICMP{ip_ver} sockets."),
+            format!("Cannot create ICMP{ip_ver} sockets."),
         ))
     } else {
         // other error -> let it bubble up normally
@@ -83,6 +114,33 @@ pub(crate) fn nice_permission_error(err: &Error, ip_ver: &str) -> Box<dyn std::e
     }
 }
 
+/// Read `net.ipv4.ping_group_range` (Linux only). Despite the name, it governs
+/// unprivileged ICMP datagram sockets for both IPv4 and IPv6.
+pub(crate) fn ping_group_range() -> Option<(u32, u32)> {
+    let content: String = fs::read_to_string("/proc/sys/net/ipv4/ping_group_range").ok()?;
+    let mut parts = content.split_whitespace().map(|p: &str| p.parse::<u32>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(start)), Some(Ok(end))) => Some((start, end)),
+        _ => None,
+    }
+}
+
+/// Group IDs (real, effective and supplementary) of this process, from `/proc/self/status`.
+fn process_gids() -> Vec<u32> {
+    let status: String = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let mut gids: Vec<u32> = status
+        .lines()
+        .filter_map(|l: &str| l.strip_prefix("Gid:").or_else(|| l.strip_prefix("Groups:")))
+        .flat_map(|l: &str| {
+            l.split_whitespace()
+                .filter_map(|g: &str| g.parse::<u32>().ok())
+        })
+        .collect();
+    gids.sort_unstable();
+    gids.dedup();
+    gids
+}
+
 /// Parse a floating point number into a Duration.
 pub(crate) fn parse_float_into_duration(arg: &str) -> Result<Duration, String> {
     match arg.parse::<f64>() {
@@ -93,3 +151,45 @@ pub(crate) fn parse_float_into_duration(arg: &str) -> Result<Duration, String> {
         _ => Err(format!("Invalid time value: {arg}")),
     }
 }
+
+/// Parse a floating point number into a Duration like [parse_float_into_duration],
+/// but also accept zero (usually meaning "disabled").
+pub(crate) fn parse_float_into_duration_or_zero(arg: &str) -> Result<Duration, String> {
+    match arg.trim().parse::<f64>() {
+        Ok(0.0) => Ok(Duration::ZERO),
+        _ => parse_float_into_duration(arg.trim()),
+    }
+}
+
+/// Format a duration for humans, eg. `850ms`, `42.5s`, `3m07s` or `2h05m`.
+pub(crate) fn format_duration(d: Duration) -> String {
+    let secs: u64 = d.as_secs();
+    match secs {
+        0 => format!("{}ms", d.as_millis()),
+        1..60 => format!("{:.1}s", d.as_secs_f64()),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Format a wall-clock time in the local timezone as `YYYY-MM-DD HH:MM:SS`.
+pub(crate) fn local_time_str(t: SystemTime) -> String {
+    let secs: libc::time_t = match t.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as libc::time_t,
+        Err(_) => 0,
+    };
+    // SAFETY: localtime_r() only writes into the provided struct
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return format!("@{secs}");
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
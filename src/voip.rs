@@ -0,0 +1,181 @@
+// Copyright (c) 2025 Mikko Tanner. All rights reserved.
+// Licensed under the MIT License or the Apache License, Version 2.0.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Estimated VoIP call quality (R-factor and MOS) with a simplified ITU-T G.107
+//! E-model. Only the network dependent impairments are modelled (delay, jitter and
+//! packet loss with a codec profile), everything else uses the G.107 defaults.
+
+use std::{fmt::Display, str::FromStr};
+
+/// R0 - Is with the G.107 default values (basic signal-to-noise ratio minus
+/// simultaneous impairments)
+const R_DEFAULT: f64 = 93.2;
+/// Delay (ms) after which the delay impairment grows faster
+const DELAY_KNEE: f64 = 177.3;
+
+/// Codec impairment profile, values from ITU-T G.113 Appendix I.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Codec {
+    /// G.711 64 kbit/s with packet loss concealment
+    #[default]
+    G711,
+    /// G.729A 8 kbit/s
+    G729,
+    /// G.723.1 6.3 kbit/s
+    G723,
+}
+
+impl Codec {
+    /// Equipment impairment factor `Ie` of the codec itself.
+    pub fn ie(&self) -> f64 {
+        match self {
+            Codec::G711 => 0.0,
+            Codec::G729 => 11.0,
+            Codec::G723 => 15.0,
+        }
+    }
+
+    /// Packet loss robustness factor `Bpl` (random loss).
+    pub fn bpl(&self) -> f64 {
+        match self {
+            Codec::G711 => 25.1,
+            Codec::G729 => 19.0,
+            Codec::G723 => 16.1,
+        }
+    }
+
+    /// One-way delay (ms) added by the codec: frame size plus look-ahead.
+    pub fn delay(&self) -> f64 {
+        match self {
+            Codec::G711 => 20.0,
+            Codec::G729 => 25.0,
+            Codec::G723 => 37.5,
+        }
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Codec::G711 => write!(f, "g711"),
+            Codec::G729 => write!(f, "g729"),
+            Codec::G723 => write!(f, "g723"),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    /// Parse a codec name (case-insensitive), eg. `g711` or `G.729`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('.', "").as_str() {
+            "g711" => Ok(Codec::G711),
+            "g729" | "g729a" => Ok(Codec::G729),
+            "g723" | "g7231" => Ok(Codec::G723),
+            _ => Err(format!("Unknown codec '{s}' (valid: g711, g729, g723)")),
+        }
+    }
+}
+
+/* ---------------------------------------- */
+
+/// Call quality classes by R-factor, as used in G.109.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Quality {
+    /// R >= 80: satisfied users
+    Good,
+    /// R >= 70: some users dissatisfied
+    Fair,
+    /// R >= 60: many users dissatisfied
+    Poor,
+    /// R < 60: nearly all users dissatisfied
+    Bad,
+}
+
+/// Estimated call quality of a target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct VoipScore {
+    /// Transmission rating factor `[0 .. 100]`
+    pub r: f64,
+    /// Mean opinion score `[1.0 .. 4.5]`
+    pub mos: f64,
+}
+
+impl VoipScore {
+    /// Estimate call quality from network statistics.
+    ///
+    /// - `rtt` is the mean round-trip time and `jitter` the interarrival jitter (ms).
+    ///   The one-way delay is taken as half the RTT, plus a jitter buffer of twice the
+    ///   jitter and the codec delay.
+    /// - `loss` is the packet loss as a float `[0.0 .. 1.0]`.
+    pub fn new(codec: Codec, rtt: f64, jitter: f64, loss: f64) -> Self {
+        let delay: f64 = rtt / 2.0 + 2.0 * jitter + codec.delay();
+        let id: f64 = 0.024 * delay + 0.11 * (delay - DELAY_KNEE).max(0.0);
+
+        let ppl: f64 = 1e2 * loss.clamp(0.0, 1.0);
+        let ie_eff: f64 = codec.ie() + (95.0 - codec.ie()) * ppl / (ppl + codec.bpl());
+
+        let r: f64 = (R_DEFAULT - id - ie_eff).clamp(0.0, 100.0);
+        Self {
+            r,
+            mos: r_to_mos(r),
+        }
+    }
+
+    /// Quality class of this score.
+    pub fn quality(&self) -> Quality {
+        match self.r {
+            r if r >= 80.0 => Quality::Good,
+            r if r >= 70.0 => Quality::Fair,
+            r if r >= 60.0 => Quality::Poor,
+            _ => Quality::Bad,
+        }
+    }
+}
+
+/// Convert an R-factor to MOS (G.107 Annex B).
+pub(crate) fn r_to_mos(r: f64) -> f64 {
+    if r <= 0.0 {
+        1.0
+    } else if r >= 100.0 {
+        4.5
+    } else {
+        1.0 + 0.035 * r + r * (r - 60.0) * (100.0 - r) * 7e-6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_e_model() {
+        // perfect network: only the codec delay impairs G.711
+        let s: VoipScore = VoipScore::new(Codec::G711, 0.0, 0.0, 0.0);
+        assert!((s.r - (R_DEFAULT - 0.024 * 20.0)).abs() < 1e-9);
+        assert!(s.mos > 4.3 && s.mos <= 4.5);
+        assert_eq!(s.quality(), Quality::Good);
+
+        // the codec costs quality even without loss, and loss costs more
+        let g729: VoipScore = VoipScore::new(Codec::G729, 20.0, 1.0, 0.0);
+        assert!(g729.r < VoipScore::new(Codec::G711, 20.0, 1.0, 0.0).r);
+        let lossy: VoipScore = VoipScore::new(Codec::G711, 20.0, 1.0, 0.05);
+        assert_eq!(lossy.quality(), Quality::Fair);
+
+        // satellite-like delay crosses the knee, total loss is unusable
+        assert_eq!(
+            VoipScore::new(Codec::G711, 800.0, 5.0, 0.0).quality(),
+            Quality::Bad
+        );
+        let dead: VoipScore = VoipScore::new(Codec::G711, 20.0, 0.0, 1.0);
+        assert_eq!(dead.quality(), Quality::Bad);
+        assert!(dead.mos < 1.5);
+
+        assert_eq!(r_to_mos(-5.0), 1.0);
+        assert_eq!(r_to_mos(120.0), 4.5);
+        assert_eq!("G.729".parse::<Codec>(), Ok(Codec::G729));
+        assert!("opus".parse::<Codec>().is_err());
+    }
+}